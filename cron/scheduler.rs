@@ -1,75 +1,193 @@
 use sqlx::PgPool;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::{JoinError, JoinSet};
 use tokio_cron_scheduler::{Job, JobScheduler};
-use tracing::error;
+use tracing::{error, info};
 
-use crate::database::db::{get_all_steam_ids, store_steam_games};
-use crate::steam::{fetch_steam_games, SteamOwnedGames};
+use crate::cache::Caches;
+use crate::database::db::{
+    finish_sync_run, get_pending_sync_steam_ids, get_sync_steam_ids, get_unfinished_sync_run,
+    record_sync_failure, record_sync_success, start_sync_run, store_steam_games, try_lock_sync,
+};
+use crate::steam::{SteamClient, SteamOwnedGames};
+use crate::store::{refresh_store_metadata, StoreClient, STORE_METADATA_TTL, STORE_REFRESH_BATCH};
 
-const API_URL: &str = "https://api.steampowered.com";
+/// When the library sync runs unless `SYNC_SCHEDULE` says otherwise: every day at 3 AM
+pub const DEFAULT_SYNC_SCHEDULE: &str = "0 3 * * *";
 
-/// Function to sync the database with updated games
+/// How often cache hit and miss counters are logged
+const CACHE_STATS_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// How many accounts are synced at once. Requests still share the Steam rate limit; this
+/// just keeps one account's retries from holding up everyone behind it.
+const SYNC_CONCURRENCY: usize = 4;
+
+/// Function to sync the database with updated games. Progress is recorded per account, so
+/// a run cut short by a restart resumes with the accounts it hadn't reached. Does nothing
+/// while another run is still in progress.
 pub async fn sync_all_users_games(
     pool: &PgPool,
-    steam_api_key: &str,
+    steam: &SteamClient,
+    caches: &Arc<Caches>,
 ) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(_lock) = try_lock_sync(pool).await? else {
+        println!("A sync is already in progress; skipping this one.");
+        return Ok(());
+    };
     println!("Syncing all users' games...");
 
-    // Fetch all user steam IDs
-    match get_all_steam_ids(pool).await {
-        Ok(steam_ids) => {
-            println!("Fetched {} Steam IDs", steam_ids.len());
-
-            for steam_id in steam_ids {
-                match fetch_steam_games(API_URL, &steam_id, steam_api_key).await {
-                    Ok(games) => {
-                        let owned_games = SteamOwnedGames { games };
-                        if let Err(e) = store_steam_games(pool, &steam_id, owned_games).await {
-                            error!("Failed to store games for Steam ID {}: {:?}", steam_id, e);
-                        } else {
-                            println!("Successfully updated games for Steam ID {}", steam_id);
-                        }
-                    }
-                    Err(e) => error!("Failed to fetch games for Steam ID {}: {:?}", steam_id, e),
-                }
-            }
+    let (run_id, resumed) = match get_unfinished_sync_run(pool).await? {
+        Some(run_id) => (run_id, true),
+        None => (start_sync_run(pool).await?, false),
+    };
+    let total = get_sync_steam_ids(pool).await?.len();
+    let steam_ids = get_pending_sync_steam_ids(pool, run_id).await?;
+    println!(
+        "{} sync run {}: {} of {} Steam IDs to go",
+        if resumed { "Resuming" } else { "Starting" },
+        run_id,
+        steam_ids.len(),
+        total
+    );
+
+    let mut tasks = JoinSet::new();
+    for steam_id in steam_ids {
+        if tasks.len() >= SYNC_CONCURRENCY {
+            log_panic(tasks.join_next().await);
         }
-        Err(e) => error!("Failed to fetch user Steam IDs: {:?}", e),
+        let pool = pool.clone();
+        let steam = steam.clone();
+        let caches = Arc::clone(caches);
+        tasks
+            .spawn(async move { sync_user_games(&pool, &steam, &caches, run_id, &steam_id).await });
     }
+    while let Some(result) = tasks.join_next().await {
+        log_panic(Some(result));
+    }
+
+    let (synced, failed) = finish_sync_run(pool, run_id).await?;
+    println!(
+        "Sync run {} finished: {} updated, {} failed",
+        run_id, synced, failed
+    );
 
     Ok(())
 }
 
-/// Daily scheduler to run `sync_all_users_games`
+/// Fetches and stores one account's library, then records the outcome for run `run_id`
+async fn sync_user_games(
+    pool: &PgPool,
+    steam: &SteamClient,
+    caches: &Caches,
+    run_id: i64,
+    steam_id: &str,
+) {
+    let outcome = match steam.fetch_games(steam_id).await {
+        Ok(games) => {
+            let owned_games = SteamOwnedGames { games };
+            let stored = store_steam_games(pool, steam_id, owned_games).await;
+            caches.invalidate_user(steam_id).await;
+            stored.map_err(|e| format!("failed to store games: {}", e))
+        }
+        // Private profiles count as failures too, and keep their last synced games
+        Err(e) => Err(e.to_string()),
+    };
+
+    let recorded = match outcome {
+        Ok(()) => {
+            println!("Successfully updated games for Steam ID {}", steam_id);
+            record_sync_success(pool, Some(run_id), steam_id).await
+        }
+        Err(e) => {
+            error!("Failed to sync games for Steam ID {}: {}", steam_id, e);
+            record_sync_failure(pool, run_id, steam_id, &e).await
+        }
+    };
+    if let Err(e) = recorded {
+        error!(
+            "Failed to record sync status for Steam ID {}: {:?}",
+            steam_id, e
+        );
+    }
+}
+
+/// A panicking sync task only loses that account; the run carries on
+fn log_panic(result: Option<Result<(), JoinError>>) {
+    if let Some(Err(e)) = result {
+        error!("Sync task failed: {:?}", e);
+    }
+}
+
+/// Daily scheduler to run `sync_all_users_games`. `schedule` is a cron expression such as
+/// [`DEFAULT_SYNC_SCHEDULE`].
 pub async fn start_scheduler(
     pool: PgPool,
-    steam_api_key: String,
+    steam: SteamClient,
+    store: StoreClient,
+    schedule: &str,
+    caches: Arc<Caches>,
 ) -> Result<JobScheduler, Box<dyn std::error::Error>> {
     let scheduler = JobScheduler::new().await?;
 
     let pool = std::sync::Arc::new(pool);
-    let api_key = std::sync::Arc::new(steam_api_key);
 
-    // Schedule a job to run every day at 3 AM
+    // Run the sync (by default every day at 3 AM)
     let job = {
         let pool = Arc::clone(&pool);
-        let api_key = Arc::clone(&api_key);
+        let steam = steam.clone();
+        let store = store.clone();
+        let caches = Arc::clone(&caches);
 
-        Job::new_async("0 3 * * *", move |_uuid, _l| {
+        Job::new_async(schedule, move |_uuid, _l| {
             let pool = Arc::clone(&pool);
-            let api_key = Arc::clone(&api_key);
+            let steam = steam.clone();
+            let store = store.clone();
+            let caches = Arc::clone(&caches);
             Box::pin(async move {
-                if let Err(e) = sync_all_users_games(&pool, &api_key).await {
-                    error!("Daily sync failed: {:?}", e);
+                if let Err(e) = sync_all_users_games(&pool, &steam, &caches).await {
+                    error!("Scheduled sync failed: {:?}", e);
                 } else {
-                    println!("Daily sync completed.");
+                    println!("Scheduled sync completed.");
+                }
+
+                // Newly synced games need store metadata; old entries expire after the TTL
+                match refresh_store_metadata(&pool, &store, STORE_METADATA_TTL, STORE_REFRESH_BATCH)
+                    .await
+                {
+                    Ok(count) => println!("Refreshed store metadata for {} apps.", count),
+                    Err(e) => error!("Store metadata refresh failed: {:?}", e),
                 }
             })
         })?
     };
 
+    // Log cache hit rates for monitoring
+    let cache_stats = {
+        let caches = Arc::clone(&caches);
+
+        Job::new_repeated_async(CACHE_STATS_INTERVAL, move |_uuid, _l| {
+            let caches = Arc::clone(&caches);
+            Box::pin(async move {
+                info!("Library cache: {}", caches.libraries.stats());
+                info!("Recommendation cache: {}", caches.recommendations.stats());
+            })
+        })?
+    };
+
     scheduler.add(job).await?;
+    scheduler.add(cache_stats).await?;
     scheduler.start().await?;
 
+    // Finish a sync the last shutdown interrupted instead of waiting for the next day
+    if let Some(run_id) = get_unfinished_sync_run(&pool).await? {
+        tokio::spawn(async move {
+            match sync_all_users_games(&pool, &steam, &caches).await {
+                Ok(()) => println!("Interrupted sync run {} completed.", run_id),
+                Err(e) => error!("Resumed sync failed: {:?}", e),
+            }
+        });
+    }
+
     Ok(scheduler)
 }
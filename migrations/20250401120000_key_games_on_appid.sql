@@ -0,0 +1,10 @@
+-- Rows stored before appid was captured cannot be keyed; the next sync repopulates them.
+DELETE FROM games;
+
+ALTER TABLE games
+    ADD COLUMN appid INT NOT NULL,
+    ADD COLUMN img_icon_url TEXT,
+    ADD COLUMN playtime_2weeks INT,
+    ADD COLUMN rtime_last_played BIGINT,
+    DROP CONSTRAINT games_steam_id_name_key,
+    ADD CONSTRAINT games_steam_id_appid_key UNIQUE (steam_id, appid);
@@ -0,0 +1,35 @@
+-- Steam store metadata per appid, refreshed by the scheduler once fetched_at is older than the TTL
+CREATE TABLE IF NOT EXISTS store_apps (
+    appid INT PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- FALSE when the store has no page for the app (delisted, tools, etc.)
+    available BOOLEAN NOT NULL DEFAULT TRUE,
+    is_free BOOLEAN NOT NULL DEFAULT FALSE,
+    price_cents INT,
+    currency TEXT,
+    metacritic_score INT,
+    release_date TEXT,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS store_app_genres (
+    appid INT NOT NULL REFERENCES store_apps (appid) ON DELETE CASCADE,
+    genre TEXT NOT NULL,
+    PRIMARY KEY (appid, genre)
+);
+
+CREATE TABLE IF NOT EXISTS store_app_categories (
+    appid INT NOT NULL REFERENCES store_apps (appid) ON DELETE CASCADE,
+    category TEXT NOT NULL,
+    PRIMARY KEY (appid, category)
+);
+
+CREATE TABLE IF NOT EXISTS store_app_tags (
+    appid INT NOT NULL REFERENCES store_apps (appid) ON DELETE CASCADE,
+    tag TEXT NOT NULL,
+    votes INT NOT NULL DEFAULT 0,
+    PRIMARY KEY (appid, tag)
+);
+
+CREATE INDEX IF NOT EXISTS store_app_genres_genre_idx ON store_app_genres (LOWER(genre));
+CREATE INDEX IF NOT EXISTS store_app_tags_tag_idx ON store_app_tags (LOWER(tag));
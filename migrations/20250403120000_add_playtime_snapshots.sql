@@ -0,0 +1,18 @@
+-- Append-only playtime history. A row is written whenever a sync sees a game's lifetime
+-- playtime change (or sees the game for the first time), with the minutes played since
+-- the previous row for that game in delta_minutes.
+CREATE TABLE playtime_snapshots (
+    id BIGSERIAL PRIMARY KEY,
+    steam_id TEXT NOT NULL,
+    appid INT NOT NULL,
+    playtime_forever INT NOT NULL,
+    delta_minutes INT NOT NULL,
+    captured_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX playtime_snapshots_user_time_idx ON playtime_snapshots (steam_id, captured_at);
+CREATE INDEX playtime_snapshots_user_game_idx ON playtime_snapshots (steam_id, appid, captured_at DESC);
+
+-- Existing libraries become the baseline; nothing before now is attributed to a date
+INSERT INTO playtime_snapshots (steam_id, appid, playtime_forever, delta_minutes, captured_at)
+SELECT steam_id, appid, playtime_forever, 0, COALESCE(last_updated, NOW()) FROM games;
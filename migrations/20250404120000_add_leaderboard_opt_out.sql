@@ -0,0 +1,2 @@
+-- Members can hide themselves from !leaderboard
+ALTER TABLE users ADD COLUMN leaderboard_opt_out BOOLEAN NOT NULL DEFAULT FALSE;
@@ -0,0 +1,22 @@
+-- Per-server settings. A row is created when the bot joins a guild; every column has a
+-- default so guilds that never touch !settings behave like the old single-server bot.
+CREATE TABLE guilds (
+    guild_id BIGINT PRIMARY KEY,
+    announcement_channel_id BIGINT,
+    -- Channels where commands are answered; empty means every channel
+    allowed_channel_ids BIGINT[] NOT NULL DEFAULT '{}',
+    prefix TEXT NOT NULL DEFAULT '!',
+    llm_persona BOOLEAN NOT NULL DEFAULT TRUE,
+    sync_enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Which guilds a Discord user has used the bot in. Discord only tells us about members
+-- when they interact, so this fills in as people run commands.
+CREATE TABLE guild_members (
+    guild_id BIGINT NOT NULL REFERENCES guilds (guild_id) ON DELETE CASCADE,
+    discord_id BIGINT NOT NULL,
+    PRIMARY KEY (guild_id, discord_id)
+);
+
+CREATE INDEX guild_members_discord_id_idx ON guild_members (discord_id);
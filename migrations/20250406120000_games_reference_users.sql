@@ -0,0 +1,15 @@
+-- Synced libraries and playtime history belong to a linked user and go away with them.
+-- Rows left behind by users who were removed before this constraint existed are dropped.
+DELETE FROM games g
+WHERE NOT EXISTS (SELECT 1 FROM users u WHERE u.steam_id = g.steam_id);
+
+DELETE FROM playtime_snapshots p
+WHERE NOT EXISTS (SELECT 1 FROM users u WHERE u.steam_id = p.steam_id);
+
+ALTER TABLE games
+    ADD CONSTRAINT games_steam_id_fkey
+    FOREIGN KEY (steam_id) REFERENCES users (steam_id) ON DELETE CASCADE;
+
+ALTER TABLE playtime_snapshots
+    ADD CONSTRAINT playtime_snapshots_steam_id_fkey
+    FOREIGN KEY (steam_id) REFERENCES users (steam_id) ON DELETE CASCADE;
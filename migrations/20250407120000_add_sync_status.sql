@@ -0,0 +1,23 @@
+-- One row per scheduled library sync. A run without finished_at was interrupted, and the
+-- next sync picks it up instead of starting over.
+CREATE TABLE sync_runs (
+    id BIGSERIAL PRIMARY KEY,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ,
+    users_synced INTEGER NOT NULL DEFAULT 0,
+    users_failed INTEGER NOT NULL DEFAULT 0
+);
+
+-- Sync outcome per linked account. last_run_id marks who a run has already handled, and
+-- failing accounts wait until next_attempt so they don't hold up everyone else.
+CREATE TABLE sync_status (
+    steam_id TEXT PRIMARY KEY REFERENCES users (steam_id) ON DELETE CASCADE,
+    last_run_id BIGINT REFERENCES sync_runs (id) ON DELETE SET NULL,
+    last_success TIMESTAMPTZ,
+    last_error TEXT,
+    last_error_at TIMESTAMPTZ,
+    consecutive_failures INTEGER NOT NULL DEFAULT 0,
+    next_attempt TIMESTAMPTZ
+);
+
+CREATE INDEX sync_status_last_run_id_idx ON sync_status (last_run_id);
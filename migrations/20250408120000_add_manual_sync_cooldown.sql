@@ -0,0 +1,2 @@
+-- When each user last ran !sync, so the command can be rate limited per user
+ALTER TABLE users ADD COLUMN last_manual_sync_at TIMESTAMPTZ;
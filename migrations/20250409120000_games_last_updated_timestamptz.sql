@@ -0,0 +1,3 @@
+-- last_updated is compared against the library TTL, so store it with a time zone. Existing
+-- values were written with NOW() in the session time zone, which is how the cast reads them.
+ALTER TABLE games ALTER COLUMN last_updated TYPE TIMESTAMPTZ USING last_updated::TIMESTAMPTZ;
@@ -0,0 +1,20 @@
+-- Every AI request, for cooldowns, daily caps and the global token budget. Rows stop
+-- mattering after a day and are pruned after two.
+CREATE TABLE llm_usage (
+    id BIGSERIAL PRIMARY KEY,
+    discord_id BIGINT,
+    -- NULL for requests made in DMs
+    guild_id BIGINT,
+    tokens INTEGER NOT NULL,
+    used_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX llm_usage_discord_id_idx ON llm_usage (discord_id, used_at);
+CREATE INDEX llm_usage_guild_id_idx ON llm_usage (guild_id, used_at);
+CREATE INDEX llm_usage_used_at_idx ON llm_usage (used_at);
+
+-- Per-server overrides of the AI limits; NULL keeps the bot's default
+ALTER TABLE guilds
+    ADD COLUMN llm_cooldown_secs INTEGER,
+    ADD COLUMN llm_member_daily_limit INTEGER,
+    ADD COLUMN llm_guild_daily_limit INTEGER;
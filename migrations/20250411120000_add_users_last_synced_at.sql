@@ -0,0 +1,6 @@
+-- When each user's library was last written by a sync, even if it had no games. Existing
+-- users start from their newest stored game.
+ALTER TABLE users ADD COLUMN last_synced_at TIMESTAMPTZ;
+
+UPDATE users u
+SET last_synced_at = (SELECT MAX(g.last_updated) FROM games g WHERE g.steam_id = u.steam_id);
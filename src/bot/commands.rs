@@ -0,0 +1,516 @@
+use crate::guilds::MAX_PREFIX_CHARS;
+use crate::llm::MAX_INTENT_CHARS;
+use serenity::builder::{
+    CreateActionRow, CreateCommand, CreateCommandOption, CreateEmbed,
+    CreateInteractionResponseFollowup, CreateMessage, EditInteractionResponse,
+};
+use serenity::model::application::{
+    CommandInteraction, CommandOptionType, ResolvedOption, ResolvedValue,
+};
+use serenity::model::channel::Message;
+use serenity::model::id::{ChannelId, GuildId, RoleId};
+use serenity::model::permissions::Permissions;
+use serenity::model::user::User;
+use serenity::prelude::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Slash commands registered with Discord on startup
+pub fn slash_commands() -> Vec<CreateCommand> {
+    vec![
+        CreateCommand::new("link_steam")
+            .description("Link your Steam account")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "profile",
+                    "Steam ID, custom URL name or profile link",
+                )
+                .required(true)
+                .set_autocomplete(true),
+            ),
+        CreateCommand::new("relink_steam")
+            .description("Switch your linked Steam account")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "profile",
+                    "Steam ID, custom URL name or profile link",
+                )
+                .required(true)
+                .set_autocomplete(true),
+            ),
+        CreateCommand::new("unlink_steam")
+            .description("Unlink your Steam account and delete your data"),
+        CreateCommand::new("export_my_data")
+            .description("Get a copy of everything stored about you by DM"),
+        CreateCommand::new("top_games")
+            .description("Show your most-played games")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Integer,
+                    "count",
+                    "How many games to show",
+                )
+                .min_int_value(1)
+                .max_int_value(MAX_TOP_GAMES as u64),
+            ),
+        CreateCommand::new("stats").description("Show your library and playtime stats"),
+        CreateCommand::new("sync").description("Refresh your Steam library right now"),
+        CreateCommand::new("leaderboard")
+            .description("Rank linked members by playtime")
+            .add_option(
+                CreateCommandOption::new(CommandOptionType::String, "board", "What to rank by")
+                    .add_string_choice("Total playtime", "total")
+                    .add_string_choice("Hours in the last 7 days", "week"),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "game",
+                    "Rank by playtime in this game instead",
+                )
+                .set_autocomplete(true),
+            ),
+        CreateCommand::new("leaderboard_privacy")
+            .description("Choose whether you appear on leaderboards")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::Boolean,
+                    "visible",
+                    "Show me on leaderboards",
+                )
+                .required(true),
+            ),
+        CreateCommand::new("together")
+            .description("Find a game your group can play together")
+            .set_options(
+                (1..=MAX_GROUP_MENTIONS)
+                    .map(|i| {
+                        CreateCommandOption::new(
+                            CommandOptionType::User,
+                            format!("member{}", i),
+                            "A friend to play with",
+                        )
+                        .required(i == 1)
+                    })
+                    .chain(std::iter::once(CreateCommandOption::new(
+                        CommandOptionType::Boolean,
+                        "ai",
+                        "Also ask the AI for a game none of you own",
+                    )))
+                    .collect(),
+            ),
+        CreateCommand::new("recommend")
+            .description("Get AI-generated game recommendations")
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "query",
+                    "What you're in the mood for, e.g. chill co-op roguelike",
+                )
+                .max_length(MAX_INTENT_CHARS as u16),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "engine",
+                    "Which recommender to use",
+                )
+                .add_string_choice("AI picks from server data", "hybrid")
+                .add_string_choice("AI", "llm")
+                .add_string_choice("Server players (offline)", "cf"),
+            )
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "genre",
+                    "Only recommend games with this Steam genre or tag",
+                )
+                .set_autocomplete(true),
+            ),
+        CreateCommand::new("settings")
+            .description("Show or change this server's bot settings")
+            .default_member_permissions(Permissions::MANAGE_GUILD)
+            .dm_permission(false)
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "announcement_channel",
+                "Where the bot posts announcements",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "announcements",
+                "Turn announcements on or off",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "command_channel",
+                "Also answer commands in this channel",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "all_channels",
+                "Answer commands in every channel again",
+            ))
+            .add_option(
+                CreateCommandOption::new(
+                    CommandOptionType::String,
+                    "prefix",
+                    "Prefix for text commands",
+                )
+                .max_length(MAX_PREFIX_CHARS as u16),
+            )
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "persona",
+                "Give AI prompts a random persona for variety",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::Boolean,
+                "sync",
+                "Refresh members' libraries in the daily sync",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "ai_cooldown",
+                "Seconds between a member's AI requests, or `default`",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "ai_member_limit",
+                "AI requests per member a day, or `default`",
+            ))
+            .add_option(CreateCommandOption::new(
+                CommandOptionType::String,
+                "ai_server_limit",
+                "AI requests for the whole server a day, or `default`",
+            )),
+    ]
+}
+
+/// Most games `/top_games` shows, keeping the reply under Discord's message length limit
+pub const MAX_TOP_GAMES: usize = 25;
+
+/// Most friends `/together` takes besides the caller
+pub const MAX_GROUP_MENTIONS: usize = 7;
+
+/// Which recommender answers `!recommend`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Engine {
+    /// Local candidates ranked and explained by the LLM
+    #[default]
+    Hybrid,
+    /// The configured LLM provider on its own
+    Llm,
+    /// Offline item-item collaborative filter over the server's libraries
+    Cf,
+}
+
+impl std::str::FromStr for Engine {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hybrid" => Ok(Engine::Hybrid),
+            "llm" | "ai" => Ok(Engine::Llm),
+            "cf" => Ok(Engine::Cf),
+            other => Err(format!(
+                "Unknown engine `{}`. Use `hybrid`, `llm` or `cf`.",
+                other
+            )),
+        }
+    }
+}
+
+/// Parsed arguments of `!recommend [--engine hybrid|llm|cf] [--genre <genre>] [query...]`
+#[derive(Debug, Default, PartialEq)]
+pub struct RecommendOptions {
+    pub engine: Engine,
+    /// Steam genre or tag the results must have; multi-word genres are written with
+    /// dashes on the command line (`free-to-play`)
+    pub genre: Option<String>,
+    pub query: String,
+}
+
+impl RecommendOptions {
+    /// Pulls `--flag value` pairs out of the arguments; everything else is the query
+    pub fn from_args(args: &[&str]) -> Result<Self, String> {
+        let mut options = RecommendOptions::default();
+        let mut query = Vec::new();
+        let mut args = args.iter();
+
+        while let Some(arg) = args.next() {
+            match *arg {
+                "--engine" => {
+                    let engine = args
+                        .next()
+                        .ok_or("`--engine` needs a value: `hybrid`, `llm` or `cf`.")?;
+                    options.engine = engine.parse()?;
+                }
+                "--genre" => {
+                    let genre = args
+                        .next()
+                        .ok_or("`--genre` needs a value, e.g. `--genre roguelike`.")?;
+                    options.genre = Some(genre.to_string());
+                }
+                flag if flag.starts_with("--") => {
+                    return Err(format!("Unknown option `{}`.", flag));
+                }
+                word => query.push(word),
+            }
+        }
+
+        options.query = query.join(" ");
+        Ok(options)
+    }
+}
+
+/// Parsed arguments of `!leaderboard [total|week|game <name>|hide|show]`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum LeaderboardCommand {
+    /// Lifetime playtime across the whole library
+    #[default]
+    Total,
+    /// Playtime gained in the last 7 days
+    Week,
+    /// Lifetime playtime in one game, matched loosely by name
+    Game(String),
+    /// Opt out of leaderboards
+    Hide,
+    /// Opt back in
+    Show,
+}
+
+impl LeaderboardCommand {
+    /// Anything that isn't a board name or `hide`/`show` is taken as a game name, so
+    /// `!leaderboard hades` works as well as `!leaderboard game hades`
+    pub fn from_args(args: &[&str]) -> Result<Self, String> {
+        match args {
+            [] => Ok(LeaderboardCommand::Total),
+            [board] if board.eq_ignore_ascii_case("total") => Ok(LeaderboardCommand::Total),
+            [board] if board.eq_ignore_ascii_case("week") => Ok(LeaderboardCommand::Week),
+            [setting] if setting.eq_ignore_ascii_case("hide") => Ok(LeaderboardCommand::Hide),
+            [setting] if setting.eq_ignore_ascii_case("show") => Ok(LeaderboardCommand::Show),
+            [game] if game.eq_ignore_ascii_case("game") => {
+                Err("`!leaderboard game` needs a game name, e.g. `!leaderboard game hades`.".into())
+            }
+            [game, name @ ..] if game.eq_ignore_ascii_case("game") => {
+                Ok(LeaderboardCommand::Game(name.join(" ")))
+            }
+            name => Ok(LeaderboardCommand::Game(name.join(" "))),
+        }
+    }
+}
+
+/// Finds a string option by name on a slash command
+pub fn string_option<'a>(options: &[ResolvedOption<'a>], name: &str) -> Option<&'a str> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| match o.value {
+            ResolvedValue::String(s) => Some(s),
+            _ => None,
+        })
+}
+
+/// Finds an integer option by name on a slash command
+pub fn integer_option(options: &[ResolvedOption<'_>], name: &str) -> Option<i64> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| match o.value {
+            ResolvedValue::Integer(i) => Some(i),
+            _ => None,
+        })
+}
+
+/// Finds a boolean option by name on a slash command
+pub fn boolean_option(options: &[ResolvedOption<'_>], name: &str) -> Option<bool> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| match o.value {
+            ResolvedValue::Boolean(b) => Some(b),
+            _ => None,
+        })
+}
+
+/// Finds a channel option by name on a slash command
+pub fn channel_option(options: &[ResolvedOption<'_>], name: &str) -> Option<ChannelId> {
+    options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| match o.value {
+            ResolvedValue::Channel(channel) => Some(channel.id),
+            _ => None,
+        })
+}
+
+/// Every user option on a slash command, in option order
+pub fn user_options(options: &[ResolvedOption<'_>]) -> Vec<User> {
+    options
+        .iter()
+        .filter_map(|o| match o.value {
+            ResolvedValue::User(user, _) => Some(user.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// A message the bot sends back, independent of how the command was invoked
+#[derive(Default)]
+pub struct Reply {
+    pub content: Option<String>,
+    pub embeds: Vec<CreateEmbed>,
+    pub components: Vec<CreateActionRow>,
+}
+
+impl Reply {
+    pub fn new(content: impl Into<String>) -> Self {
+        Reply {
+            content: Some(content.into()),
+            ..Default::default()
+        }
+    }
+
+    pub fn embed(mut self, embed: CreateEmbed) -> Self {
+        self.embeds.push(embed);
+        self
+    }
+
+    pub fn components(mut self, components: Vec<CreateActionRow>) -> Self {
+        self.components = components;
+        self
+    }
+}
+
+/// Who ran a command and where to answer, for both `!` prefix messages and slash commands
+pub struct Invocation {
+    pub channel_id: ChannelId,
+    /// `None` in DMs
+    pub guild_id: Option<GuildId>,
+    pub author: User,
+    /// The author's roles in the guild, for working out permissions of prefix commands
+    roles: Vec<RoleId>,
+    /// The author's permissions in the channel, which Discord sends with slash commands
+    permissions: Option<Permissions>,
+    interaction: Option<CommandInteraction>,
+    /// The prefix a prefix command was typed with; `None` for slash commands
+    prefix: Option<String>,
+    /// Whether the deferred slash command response has been filled in yet
+    responded: AtomicBool,
+}
+
+impl From<&CommandInteraction> for Invocation {
+    fn from(command: &CommandInteraction) -> Self {
+        Invocation {
+            channel_id: command.channel_id,
+            guild_id: command.guild_id,
+            author: command.user.clone(),
+            roles: Vec::new(),
+            permissions: command
+                .member
+                .as_ref()
+                .and_then(|member| member.permissions),
+            interaction: Some(command.clone()),
+            prefix: None,
+            responded: AtomicBool::new(false),
+        }
+    }
+}
+
+impl Invocation {
+    /// A prefix command typed as `prefix` followed by the command
+    pub fn from_message(msg: &Message, prefix: &str) -> Self {
+        Invocation {
+            channel_id: msg.channel_id,
+            guild_id: msg.guild_id,
+            author: msg.author.clone(),
+            roles: msg
+                .member
+                .as_ref()
+                .map(|member| member.roles.clone())
+                .unwrap_or_default(),
+            permissions: None,
+            interaction: None,
+            prefix: Some(prefix.to_string()),
+            responded: AtomicBool::new(false),
+        }
+    }
+
+    /// How to run another command the way this one was run, in backticks: `prefix_usage`
+    /// after the server's prefix, or `slash_usage` as a slash command
+    pub fn command(&self, prefix_usage: &str, slash_usage: &str) -> String {
+        match &self.prefix {
+            Some(prefix) => format!("`{}{}`", prefix, prefix_usage),
+            None => format!("`/{}`", slash_usage),
+        }
+    }
+
+    /// Whether the author may change server settings: the owner, or anyone with
+    /// Administrator or Manage Server. Always false in DMs.
+    pub async fn is_admin(&self, ctx: &Context) -> serenity::Result<bool> {
+        let Some(guild_id) = self.guild_id else {
+            return Ok(false);
+        };
+        if let Some(permissions) = self.permissions {
+            return Ok(permissions.administrator() || permissions.manage_guild());
+        }
+
+        // Messages only carry role ids, so add up the guild's role permissions ourselves
+        let guild = guild_id.to_partial_guild(&ctx.http).await?;
+        if guild.owner_id == self.author.id {
+            return Ok(true);
+        }
+        let permissions = guild
+            .roles
+            .values()
+            .filter(|role| role.id.get() == guild_id.get() || self.roles.contains(&role.id))
+            .fold(Permissions::empty(), |acc, role| acc | role.permissions);
+        Ok(permissions.administrator() || permissions.manage_guild())
+    }
+
+    /// Sends a plain text reply
+    pub async fn say(
+        &self,
+        ctx: &Context,
+        content: impl Into<String>,
+    ) -> serenity::Result<Message> {
+        self.send(ctx, Reply::new(content)).await
+    }
+
+    /// Sends a reply to the channel, or to the interaction for slash commands.
+    /// The first reply to a slash command fills in the deferred "thinking..." response and
+    /// every later one is sent as a followup.
+    pub async fn send(&self, ctx: &Context, reply: Reply) -> serenity::Result<Message> {
+        match &self.interaction {
+            Some(command) if !self.responded.swap(true, Ordering::SeqCst) => {
+                let mut response = EditInteractionResponse::new()
+                    .embeds(reply.embeds)
+                    .components(reply.components);
+                if let Some(content) = reply.content {
+                    response = response.content(content);
+                }
+                command.edit_response(&ctx.http, response).await
+            }
+            Some(command) => {
+                let mut followup = CreateInteractionResponseFollowup::new()
+                    .embeds(reply.embeds)
+                    .components(reply.components);
+                if let Some(content) = reply.content {
+                    followup = followup.content(content);
+                }
+                command.create_followup(&ctx.http, followup).await
+            }
+            None => {
+                let mut message = CreateMessage::new()
+                    .embeds(reply.embeds)
+                    .components(reply.components);
+                if let Some(content) = reply.content {
+                    message = message.content(content);
+                }
+                self.channel_id.send_message(&ctx.http, message).await
+            }
+        }
+    }
+}
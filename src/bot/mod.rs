@@ -0,0 +1,1957 @@
+mod commands;
+
+pub use commands::{
+    slash_commands, Engine, Invocation, LeaderboardCommand, RecommendOptions, Reply,
+};
+
+use crate::cache::{Caches, RecommendationKey, StoredLibrary};
+use crate::collaborative::{self, CfRecommendation};
+use crate::database::db;
+use crate::genres::{complete_genres, label_counts, GenreFilter};
+use crate::guilds::{GuildSettings, SettingsChange, DEFAULT_PREFIX, SETTINGS_HELP};
+use crate::leaderboard::{complete_games, find_game, page_count, render_page, LeaderboardEntry};
+use crate::llm::{sanitize_intent, LLMClient, Recommendation, RecommendationRequest, TokenUsage};
+use crate::quota::{LlmLimits, QuotaExceeded, ESTIMATED_TOKENS_PER_REQUEST};
+use crate::stats::{format_age, format_hours, LibraryStats, SyncChanges};
+use crate::steam::{
+    parse_steam_id_input, SteamClient, SteamError, SteamGame, SteamIdInput, SteamProfile,
+};
+use crate::store::StoreClient;
+use crate::together::{rank_shared_games, SHARED_GAMES_SHOWN};
+use chrono::{DateTime, Utc};
+use commands::{
+    boolean_option, channel_option, integer_option, string_option, user_options,
+    MAX_GROUP_MENTIONS, MAX_TOP_GAMES,
+};
+use serenity::all::{
+    ButtonStyle, Command, CommandInteraction, ComponentInteractionCollector, CreateActionRow,
+    CreateAttachment, CreateAutocompleteResponse, CreateButton, CreateEmbed, CreateEmbedFooter,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage, EditMessage,
+    Interaction,
+};
+use serenity::async_trait;
+use serenity::model::channel::Message;
+use serenity::model::gateway::Ready;
+use serenity::model::guild::Guild;
+use serenity::model::id::{ChannelId, GuildId};
+use serenity::model::user::User;
+use serenity::prelude::*;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+const LINK_STEAM_HELP: &str = "Please provide your Steam profile after the command. \
+     Any of these work: your profile link (`https://steamcommunity.com/id/yourname`), \
+     your custom URL name (`yourname`), or your numeric Steam ID (`76561197960287930`). \
+     You can find your profile link in Steam under your name → Profile.";
+
+const PRIVATE_PROFILE_HELP: &str = "🔒 Your Steam game details are private, so I can't see \
+     your library. In Steam, go to your profile → **Edit Profile** → **Privacy Settings**, set \
+     **My profile** and **Game details** to **Public**, then try again.";
+
+const DEFAULT_TOP_GAMES: usize = 5;
+
+/// How many collaborative-filter suggestions to show
+const CF_RECOMMENDATIONS: usize = 5;
+
+/// How long leaderboard page buttons keep working after the last press
+const LEADERBOARD_TIMEOUT: Duration = Duration::from_secs(120);
+
+/// How old a stored library may get before reading it refreshes it from Steam, unless
+/// `LIBRARY_TTL_SECS` says otherwise
+pub const DEFAULT_LIBRARY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How often each user may run `!sync`
+const SYNC_COOLDOWN: Duration = Duration::from_secs(15 * 60);
+
+/// Discord's limits on embed field names and values, in characters
+const EMBED_FIELD_NAME_LIMIT: usize = 256;
+const EMBED_FIELD_VALUE_LIMIT: usize = 1024;
+
+/// Struct representing the bot, including a database pool, API key, and cache.
+pub struct Bot {
+    pub database: sqlx::PgPool,
+    /// Shared with the scheduler, so both draw from one Steam rate limit
+    pub steam: SteamClient,
+    pub llm_client: LLMClient,
+    /// Shared with the scheduler, so both draw from one store rate limit
+    pub store: StoreClient,
+    /// Settings of guilds that have used the bot since startup, kept in sync by `!settings`
+    pub guild_settings: RwLock<HashMap<GuildId, GuildSettings>>,
+    /// Whether the legacy `!` prefix commands are still answered (requires MESSAGE_CONTENT)
+    pub prefix_commands: bool,
+    /// Stored libraries older than this are refreshed from Steam when read
+    pub library_ttl: Duration,
+    /// Shared with the scheduler, which invalidates libraries as it syncs them
+    pub caches: Arc<Caches>,
+    /// Limits on AI requests; guilds can override all but the token budget
+    pub llm_limits: LlmLimits,
+}
+
+/// Why `Bot::llm_recommendations` has nothing to show
+enum LlmFailure {
+    Quota(QuotaExceeded),
+    Failed(String),
+}
+
+/// A user's games as read by `Bot::get_steam_games`
+struct Library {
+    games: Vec<SteamGame>,
+    /// When the stored copy was last synced, if it's past the TTL and Steam couldn't be
+    /// reached to refresh it
+    stale_since: Option<DateTime<Utc>>,
+}
+
+impl Library {
+    /// A note for replies built from stale data
+    fn footer(&self) -> Option<String> {
+        self.stale_since.map(|synced_at| {
+            format!(
+                "Last synced {}. Steam couldn't be reached to refresh it.",
+                format_age(Utc::now() - synced_at)
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl EventHandler for Bot {
+    async fn message(&self, ctx: Context, msg: Message) {
+        if !self.prefix_commands || msg.author.bot {
+            return;
+        }
+
+        let settings = match msg.guild_id {
+            Some(guild_id) => Some(self.settings_for(guild_id).await),
+            None => None,
+        };
+        let prefix = settings
+            .as_ref()
+            .map_or(DEFAULT_PREFIX, |s| s.prefix.as_str());
+        let Some(content) = msg.content.strip_prefix(prefix) else {
+            return;
+        };
+        let args: Vec<&str> = content.split_whitespace().collect();
+        if args.is_empty() {
+            return;
+        }
+
+        // Admins can always reach `settings`, even after locking commands to other channels
+        if settings.as_ref().is_some_and(|s| {
+            args[0] != "settings" && !s.allows_channel(msg.channel_id.get() as i64)
+        }) {
+            return;
+        }
+
+        let invocation = Invocation::from_message(&msg, prefix);
+        self.record_member(&invocation).await;
+        match args[0] {
+            "link_steam" => {
+                if args.len() == 2 {
+                    self.handle_link_steam(&ctx, &invocation, args[1]).await;
+                } else {
+                    // Tell the user they need to provide a Steam ID
+                    if let Err(e) = invocation.say(&ctx, LINK_STEAM_HELP).await {
+                        eprintln!("Error sending message: {:?}", e);
+                    }
+                }
+            }
+            "relink_steam" => {
+                if args.len() == 2 {
+                    self.handle_relink_steam(&ctx, &invocation, args[1]).await;
+                } else {
+                    let _ = invocation.say(&ctx, LINK_STEAM_HELP).await;
+                }
+            }
+            "unlink_steam" => self.handle_unlink_steam(&ctx, &invocation).await,
+            "export_my_data" => self.export_my_data(&ctx, &invocation).await,
+            "top_games" => {
+                let count = args
+                    .get(1)
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .unwrap_or(DEFAULT_TOP_GAMES);
+                if count == 0 {
+                    let example = invocation.command("top_games 10", "top_games count:10");
+                    let _ = invocation
+                        .say(
+                            &ctx,
+                            format!("⚠️ Ask for at least one game, e.g. {}.", example),
+                        )
+                        .await;
+                    return;
+                }
+                let count = count.clamp(1, MAX_TOP_GAMES);
+                self.display_top_games(&ctx, &invocation, count).await;
+            }
+            "stats" => self.display_stats(&ctx, &invocation).await,
+            "sync" => self.sync_library(&ctx, &invocation).await,
+            "together" => {
+                let members: Vec<User> = msg.mentions.iter().filter(|u| !u.bot).cloned().collect();
+                let ask_ai = args.contains(&"--ai");
+                self.play_together(&ctx, &invocation, members, ask_ai).await;
+            }
+            "leaderboard" => match LeaderboardCommand::from_args(&args[1..]) {
+                Ok(board) => self.show_leaderboard(&ctx, &invocation, board).await,
+                Err(e) => {
+                    let _ = invocation.say(&ctx, format!("⚠️ {}", e)).await;
+                }
+            },
+            "recommend" => match RecommendOptions::from_args(&args[1..]) {
+                Ok(options) => self.recommend_games(&ctx, &invocation, options).await,
+                Err(e) => {
+                    let _ = invocation.say(&ctx, format!("⚠️ {}", e)).await;
+                }
+            },
+            "settings" => match SettingsChange::from_args(&args[1..]) {
+                Ok(change) => self.update_settings(&ctx, &invocation, vec![change]).await,
+                Err(e) => {
+                    let _ = invocation.say(&ctx, e).await;
+                }
+            },
+            _ => {}
+        }
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        match interaction {
+            Interaction::Command(command) => self.handle_slash_command(&ctx, &command).await,
+            Interaction::Autocomplete(command) => self.handle_autocomplete(&ctx, &command).await,
+            _ => {}
+        }
+    }
+
+    async fn guild_create(&self, _ctx: Context, guild: Guild, _is_new: Option<bool>) {
+        if let Err(e) = db::ensure_guild(&self.database, guild.id.get() as i64).await {
+            error!("Failed to create settings for guild {}: {:?}", guild.id, e);
+        }
+    }
+
+    async fn ready(&self, ctx: Context, ready: Ready) {
+        info!("{} is connected!", ready.user.name);
+
+        match Command::set_global_commands(&ctx.http, slash_commands()).await {
+            Ok(registered) => info!("Registered {} slash commands", registered.len()),
+            Err(e) => error!("Failed to register slash commands: {:?}", e),
+        }
+
+        for guild in &ready.guilds {
+            let settings = self.settings_for(guild.id).await;
+            let Some(channel_id) = settings.announcement_channel_id else {
+                continue;
+            };
+            if let Err(e) = ChannelId::new(channel_id as u64)
+                .say(&ctx.http, "🚀 Bot is online and ready!")
+                .await
+            {
+                error!("Failed to send announcement to guild {}: {:?}", guild.id, e);
+            }
+        }
+    }
+}
+
+impl Bot {
+    /// Dispatches a slash command to the same handlers used by the prefix commands
+    async fn handle_slash_command(&self, ctx: &Context, command: &CommandInteraction) {
+        // Discord only waits 3 seconds for a response, and Steam/LLM calls take longer
+        if let Err(e) = command.defer(&ctx.http).await {
+            error!("Failed to defer slash command: {:?}", e);
+            return;
+        }
+
+        let invocation = Invocation::from(command);
+        let options = command.data.options();
+        if let Some(guild_id) = command.guild_id {
+            let settings = self.settings_for(guild_id).await;
+            if command.data.name != "settings"
+                && !settings.allows_channel(command.channel_id.get() as i64)
+            {
+                let _ = invocation
+                    .say(ctx, "Commands are disabled in this channel.")
+                    .await;
+                return;
+            }
+        }
+        self.record_member(&invocation).await;
+
+        match command.data.name.as_str() {
+            "link_steam" => match string_option(&options, "profile") {
+                Some(profile) => self.handle_link_steam(ctx, &invocation, profile).await,
+                None => {
+                    let _ = invocation.say(ctx, LINK_STEAM_HELP).await;
+                }
+            },
+            "relink_steam" => match string_option(&options, "profile") {
+                Some(profile) => self.handle_relink_steam(ctx, &invocation, profile).await,
+                None => {
+                    let _ = invocation.say(ctx, LINK_STEAM_HELP).await;
+                }
+            },
+            "unlink_steam" => self.handle_unlink_steam(ctx, &invocation).await,
+            "export_my_data" => self.export_my_data(ctx, &invocation).await,
+            "top_games" => {
+                let count = integer_option(&options, "count")
+                    .map(|n| n as usize)
+                    .unwrap_or(DEFAULT_TOP_GAMES);
+                self.display_top_games(ctx, &invocation, count).await;
+            }
+            "stats" => self.display_stats(ctx, &invocation).await,
+            "sync" => self.sync_library(ctx, &invocation).await,
+            "together" => {
+                let members = user_options(&options);
+                let ask_ai = boolean_option(&options, "ai").unwrap_or(false);
+                self.play_together(ctx, &invocation, members, ask_ai).await;
+            }
+            "leaderboard" => {
+                let board = match (
+                    string_option(&options, "game"),
+                    string_option(&options, "board"),
+                ) {
+                    (Some(game), _) => LeaderboardCommand::Game(game.to_string()),
+                    (None, Some("week")) => LeaderboardCommand::Week,
+                    (None, _) => LeaderboardCommand::Total,
+                };
+                self.show_leaderboard(ctx, &invocation, board).await;
+            }
+            "leaderboard_privacy" => {
+                let board = match boolean_option(&options, "visible") {
+                    Some(false) => LeaderboardCommand::Hide,
+                    _ => LeaderboardCommand::Show,
+                };
+                self.show_leaderboard(ctx, &invocation, board).await;
+            }
+            "recommend" => {
+                let engine = string_option(&options, "engine")
+                    .and_then(|e| e.parse().ok())
+                    .unwrap_or_default();
+                let options = RecommendOptions {
+                    engine,
+                    genre: string_option(&options, "genre").map(str::to_string),
+                    query: string_option(&options, "query")
+                        .unwrap_or_default()
+                        .to_string(),
+                };
+                self.recommend_games(ctx, &invocation, options).await;
+            }
+            "settings" => {
+                let mut changes = Vec::new();
+                if let Some(channel) = channel_option(&options, "announcement_channel") {
+                    changes.push(SettingsChange::Announce(Some(channel.get() as i64)));
+                } else if boolean_option(&options, "announcements") == Some(false) {
+                    changes.push(SettingsChange::Announce(None));
+                }
+                if boolean_option(&options, "all_channels") == Some(true) {
+                    changes.push(SettingsChange::Channels(Vec::new()));
+                }
+                if let Some(channel) = channel_option(&options, "command_channel") {
+                    changes.push(SettingsChange::AddChannel(channel.get() as i64));
+                }
+                if let Some(prefix) = string_option(&options, "prefix") {
+                    match SettingsChange::from_args(&["prefix", prefix]) {
+                        Ok(change) => changes.push(change),
+                        Err(e) => {
+                            let _ = invocation.say(ctx, e).await;
+                            return;
+                        }
+                    }
+                }
+                if let Some(enabled) = boolean_option(&options, "persona") {
+                    changes.push(SettingsChange::Persona(enabled));
+                }
+                if let Some(enabled) = boolean_option(&options, "sync") {
+                    changes.push(SettingsChange::Sync(enabled));
+                }
+                for setting in ["ai_cooldown", "ai_member_limit", "ai_server_limit"] {
+                    if let Some(value) = string_option(&options, setting) {
+                        match SettingsChange::from_args(&[setting, value]) {
+                            Ok(change) => changes.push(change),
+                            Err(e) => {
+                                let _ = invocation.say(ctx, e).await;
+                                return;
+                            }
+                        }
+                    }
+                }
+                self.update_settings(ctx, &invocation, changes).await;
+            }
+            other => {
+                error!("Received unknown slash command: {}", other);
+                let _ = invocation.say(ctx, "Unknown command.").await;
+            }
+        }
+    }
+
+    /// Answers autocomplete requests while the user is still typing an option
+    async fn handle_autocomplete(&self, ctx: &Context, command: &CommandInteraction) {
+        let Some(focused) = command.data.autocomplete() else {
+            return;
+        };
+
+        let mut choices = CreateAutocompleteResponse::new();
+        match (command.data.name.as_str(), focused.name) {
+            ("link_steam" | "relink_steam", "profile") => {
+                // Show what the pasted text normalizes to so users can tell it was understood
+                match parse_steam_id_input(focused.value) {
+                    Some(SteamIdInput::SteamId64(id)) => {
+                        choices = choices.add_string_choice(format!("Steam ID {}", id), id);
+                    }
+                    Some(SteamIdInput::Vanity(vanity)) => {
+                        choices =
+                            choices.add_string_choice(format!("Custom URL /id/{}", vanity), vanity);
+                    }
+                    None => {}
+                }
+            }
+            ("recommend", "genre") => match db::get_app_labels(&self.database).await {
+                Ok(labels) => {
+                    for genre in complete_genres(focused.value, &label_counts(&labels)) {
+                        choices = choices.add_string_choice(genre.clone(), genre);
+                    }
+                }
+                Err(e) => error!("Error loading genres for autocomplete: {:?}", e),
+            },
+            ("leaderboard", "game") => {
+                let Some(guild_id) = command.guild_id else {
+                    return;
+                };
+                match db::get_owned_game_names(&self.database, guild_id.get() as i64).await {
+                    Ok(games) => {
+                        for game in complete_games(focused.value, &games) {
+                            choices = choices.add_string_choice(game.clone(), game);
+                        }
+                    }
+                    Err(e) => error!("Error loading games for autocomplete: {:?}", e),
+                }
+            }
+            _ => {}
+        }
+
+        if let Err(e) = command
+            .create_response(&ctx.http, CreateInteractionResponse::Autocomplete(choices))
+            .await
+        {
+            error!("Failed to send autocomplete choices: {:?}", e);
+        }
+    }
+
+    /// A guild's settings from the cache, loading them on first use. Falls back to the
+    /// defaults without caching them when the database can't be reached.
+    async fn settings_for(&self, guild_id: GuildId) -> GuildSettings {
+        if let Some(settings) = self.guild_settings.read().await.get(&guild_id) {
+            return settings.clone();
+        }
+
+        let id = guild_id.get() as i64;
+        match db::get_guild_settings(&self.database, id).await {
+            Ok(settings) => {
+                let settings = settings.unwrap_or_else(|| GuildSettings::new(id));
+                self.guild_settings
+                    .write()
+                    .await
+                    .insert(guild_id, settings.clone());
+                settings
+            }
+            Err(e) => {
+                error!("Error loading settings for guild {}: {:?}", guild_id, e);
+                GuildSettings::new(id)
+            }
+        }
+    }
+
+    /// Remembers that the author uses the bot in this guild, which scopes leaderboards and
+    /// server recommendations to the guild's own members
+    async fn record_member(&self, invocation: &Invocation) {
+        let Some(guild_id) = invocation.guild_id else {
+            return;
+        };
+        if let Err(e) = db::add_guild_member(
+            &self.database,
+            guild_id.get() as i64,
+            invocation.author.id.get() as i64,
+        )
+        .await
+        {
+            error!("Error recording guild member: {:?}", e);
+        }
+    }
+
+    /// Handles `!settings` and `/settings`. Showing the settings is open to everyone;
+    /// changing them needs Manage Server.
+    pub async fn update_settings(
+        &self,
+        ctx: &Context,
+        invocation: &Invocation,
+        changes: Vec<SettingsChange>,
+    ) {
+        let Some(guild_id) = invocation.guild_id else {
+            let _ = invocation.say(ctx, "Settings only exist in servers.").await;
+            return;
+        };
+
+        let changing = changes.iter().any(|c| *c != SettingsChange::Show);
+        if changing {
+            match invocation.is_admin(ctx).await {
+                Ok(true) => {}
+                Ok(false) => {
+                    let _ = invocation
+                        .say(ctx, "Only members with Manage Server can change settings.")
+                        .await;
+                    return;
+                }
+                Err(e) => {
+                    error!("Error checking permissions: {:?}", e);
+                    let _ = invocation
+                        .say(
+                            ctx,
+                            "Couldn't check your permissions. Please try again later.",
+                        )
+                        .await;
+                    return;
+                }
+            }
+        }
+
+        let mut settings = self.settings_for(guild_id).await;
+        if changing {
+            for change in changes {
+                settings.apply(change);
+            }
+            if let Err(e) = db::save_guild_settings(&self.database, &settings).await {
+                error!("Error saving guild settings: {:?}", e);
+                let _ = invocation
+                    .say(ctx, "Database error. Please try again later.")
+                    .await;
+                return;
+            }
+            self.guild_settings
+                .write()
+                .await
+                .insert(guild_id, settings.clone());
+        }
+
+        let embed = CreateEmbed::new()
+            .title("⚙️ Server settings")
+            .description(settings.describe())
+            .footer(CreateEmbedFooter::new(
+                SETTINGS_HELP.lines().next().unwrap_or_default(),
+            ));
+        if let Err(e) = invocation.send(ctx, Reply::default().embed(embed)).await {
+            error!("Failed to send settings: {:?}", e);
+        }
+    }
+
+    /// Helper to retrieve a user's Steam games.
+    /// Reads the database, first refreshing from Steam if the stored library is older than
+    /// `library_ttl`. When Steam is unavailable the stale copy is returned and marked as such.
+    async fn get_steam_games(&self, steam_id: &str) -> Option<Library> {
+        let stored = match self.stored_library(steam_id).await {
+            Ok(stored) => stored,
+            Err(e) => {
+                error!("Error fetching games from database: {:?}", e);
+                return None;
+            }
+        };
+        let synced_at = stored.synced_at;
+        let ttl = chrono::Duration::from_std(self.library_ttl).unwrap_or(chrono::Duration::MAX);
+        let mut stale_since = None;
+
+        if synced_at.is_none_or(|synced_at| Utc::now() - synced_at > ttl) {
+            match self.refresh_library(steam_id).await {
+                Ok(games) if !games.is_empty() => {
+                    return Some(Library {
+                        games,
+                        stale_since: None,
+                    })
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    error!("Failed to refresh stale library for {}: {}", steam_id, e);
+                    stale_since = synced_at;
+                }
+            }
+        }
+
+        if stored.games.is_empty() {
+            error!("No games found in the database for steam_id: {}", steam_id);
+            return None;
+        }
+        Some(Library {
+            games: stored.games,
+            stale_since,
+        })
+    }
+
+    /// A user's stored library and its sync time, from the cache or the database
+    async fn stored_library(&self, steam_id: &str) -> Result<StoredLibrary, Arc<sqlx::Error>> {
+        self.caches
+            .libraries
+            .get_or_try_insert(steam_id.to_string(), async {
+                let (games, synced_at) = tokio::try_join!(
+                    db::get_user_games(&self.database, steam_id),
+                    db::get_games_last_updated(&self.database, steam_id),
+                )?;
+                Ok(StoredLibrary { games, synced_at })
+            })
+            .await
+    }
+
+    /// Fetches a library from Steam and stores it, as the scheduled sync would
+    async fn refresh_library(&self, steam_id: &str) -> Result<Vec<SteamGame>, String> {
+        let games = self
+            .steam
+            .fetch_games(steam_id)
+            .await
+            .map_err(|e| e.to_string())?;
+        let owned_games = crate::steam::SteamOwnedGames {
+            games: games.clone(),
+        };
+        db::store_steam_games(&self.database, steam_id, owned_games)
+            .await
+            .map_err(|e| format!("failed to store games: {}", e))?;
+        self.caches.invalidate_user(steam_id).await;
+        if let Err(e) = db::record_sync_success(&self.database, None, steam_id).await {
+            error!("Failed to record sync status for {}: {:?}", steam_id, e);
+        }
+        Ok(games)
+    }
+
+    /// Handles the `!link_steam <steam_id | vanity | profile url>` command
+    pub async fn handle_link_steam(
+        &self,
+        ctx: &Context,
+        invocation: &Invocation,
+        steam_input: &str,
+    ) {
+        let discord_id = invocation.author.id.get() as i64;
+        match db::get_steam_id(&self.database, discord_id).await {
+            Ok(Some(_)) => {
+                let message = format!(
+                    "You already have a linked Steam ID! Use {} to switch accounts or {} to \
+                     remove it.",
+                    invocation.command("relink_steam <profile>", "relink_steam"),
+                    invocation.command("unlink_steam", "unlink_steam"),
+                );
+                let _ = invocation.say(ctx, message).await;
+                return;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!("Error checking if user exists: {:?}", e);
+                let _ = invocation
+                    .say(
+                        ctx,
+                        "⚠️ Error checking your Steam ID in the database. Try again later.",
+                    )
+                    .await;
+                return;
+            }
+        }
+
+        let Some((steam_id, profile)) = self.resolve_profile(ctx, invocation, steam_input).await
+        else {
+            return;
+        };
+
+        let confirmation_message = format!(
+            "Here is the Steam profile with the associated Steam ID: **{}**.\n{}\
+        Press **Yes** within 30 seconds to confirm linking, or **No** to cancel.",
+            profile.personaname,
+            private_profile_warning(&profile)
+        );
+        let Some(confirmed) = self
+            .confirm(ctx, invocation, "link_steam", confirmation_message)
+            .await
+        else {
+            let _ = invocation.say(ctx, "No confirmation received. Please run the command again if you wish to link your Steam account.").await;
+            return;
+        };
+
+        if confirmed {
+            let author_name = &invocation.author.name;
+
+            if let Err(e) = db::link_steam(&self.database, author_name, discord_id, &steam_id).await
+            {
+                error!("Database error linking Steam ID: {:?}", e);
+                let _ = invocation
+                    .say(ctx, "Failed to link Steam ID. Please try again later.")
+                    .await;
+                return;
+            }
+
+            let _ = invocation
+                .say(
+                    ctx,
+                    format!(
+                        "Successfully linked Steam ID `{}` to your Discord account!",
+                        steam_id
+                    ),
+                )
+                .await;
+
+            self.store_linked_games(ctx, invocation, &steam_id).await;
+        } else {
+            let _ = invocation.say(ctx, "Canceled. Please run the command again if you wish to link your Steam account.").await;
+        }
+    }
+
+    /// Handles `!relink_steam <profile>`: switches a linked user to another Steam account
+    /// and drops the old account's library and history
+    pub async fn handle_relink_steam(
+        &self,
+        ctx: &Context,
+        invocation: &Invocation,
+        steam_input: &str,
+    ) {
+        let discord_id = invocation.author.id.get() as i64;
+        let current = match db::get_steam_id(&self.database, discord_id).await {
+            Ok(Some(steam_id)) => steam_id,
+            Ok(None) => {
+                let _ = invocation.say(ctx, not_linked_message(invocation)).await;
+                return;
+            }
+            Err(e) => {
+                error!("Error fetching Steam ID: {:?}", e);
+                let _ = invocation
+                    .say(ctx, "Database error. Please try again later.")
+                    .await;
+                return;
+            }
+        };
+
+        let Some((steam_id, profile)) = self.resolve_profile(ctx, invocation, steam_input).await
+        else {
+            return;
+        };
+        if steam_id == current {
+            let _ = invocation
+                .say(ctx, "That Steam account is already linked to you.")
+                .await;
+            return;
+        }
+
+        let prompt = format!(
+            "Switch your linked Steam account from `{}` to **{}** (`{}`)? Your synced library \
+             and playtime history for the old account will be deleted.\n{}\
+             Press **Yes** within 30 seconds to confirm, or **No** to cancel.",
+            current,
+            profile.personaname,
+            steam_id,
+            private_profile_warning(&profile)
+        );
+        match self.confirm(ctx, invocation, "relink_steam", prompt).await {
+            Some(true) => {}
+            Some(false) => {
+                let _ = invocation.say(ctx, "Canceled. Nothing was changed.").await;
+                return;
+            }
+            None => {
+                let _ = invocation
+                    .say(ctx, "No confirmation received. Nothing was changed.")
+                    .await;
+                return;
+            }
+        }
+
+        match db::relink_steam(&self.database, discord_id, &steam_id).await {
+            Ok(Some(previous)) => {
+                self.caches.invalidate_user(&previous).await;
+                let _ = invocation
+                    .say(
+                        ctx,
+                        format!(
+                            "Your Discord account is now linked to Steam ID `{}`.",
+                            steam_id
+                        ),
+                    )
+                    .await;
+                self.store_linked_games(ctx, invocation, &steam_id).await;
+            }
+            Ok(None) => {
+                let _ = invocation.say(ctx, not_linked_message(invocation)).await;
+            }
+            Err(e) => {
+                error!("Database error relinking Steam ID: {:?}", e);
+                let _ = invocation
+                    .say(
+                        ctx,
+                        "Failed to switch Steam accounts. Please try again later.",
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Handles `!unlink_steam`: deletes the user and everything synced for them after a
+    /// confirmation
+    pub async fn handle_unlink_steam(&self, ctx: &Context, invocation: &Invocation) {
+        let discord_id = invocation.author.id.get() as i64;
+        let steam_id = match db::get_steam_id(&self.database, discord_id).await {
+            Ok(Some(steam_id)) => steam_id,
+            Ok(None) => {
+                let _ = invocation
+                    .say(ctx, "You don't have a linked Steam ID.")
+                    .await;
+                return;
+            }
+            Err(e) => {
+                error!("Error fetching Steam ID: {:?}", e);
+                let _ = invocation
+                    .say(ctx, "Database error. Please try again later.")
+                    .await;
+                return;
+            }
+        };
+
+        let prompt = format!(
+            "Unlink Steam ID `{}` and delete your synced library, playtime history and \
+             leaderboard entries? This can't be undone.\n\
+             Press **Yes** within 30 seconds to confirm, or **No** to cancel.",
+            steam_id
+        );
+        match self.confirm(ctx, invocation, "unlink_steam", prompt).await {
+            Some(true) => {}
+            Some(false) => {
+                let _ = invocation.say(ctx, "Canceled. Nothing was deleted.").await;
+                return;
+            }
+            None => {
+                let _ = invocation
+                    .say(ctx, "No confirmation received. Nothing was deleted.")
+                    .await;
+                return;
+            }
+        }
+
+        let message = match db::unlink_steam(&self.database, discord_id).await {
+            Ok(Some(steam_id)) => {
+                self.caches.invalidate_user(&steam_id).await;
+                "🗑️ Your Steam account is unlinked and all your data has been deleted."
+            }
+            Ok(None) => "You don't have a linked Steam ID.",
+            Err(e) => {
+                error!("Database error unlinking Steam ID: {:?}", e);
+                "Failed to unlink your Steam ID. Please try again later."
+            }
+        };
+        let _ = invocation.say(ctx, message).await;
+    }
+
+    /// Handles `!export_my_data`: DMs the user a JSON file of everything stored about them
+    pub async fn export_my_data(&self, ctx: &Context, invocation: &Invocation) {
+        let discord_id = invocation.author.id.get() as i64;
+        let data = match db::get_user_data(&self.database, discord_id).await {
+            Ok(Some(data)) => data,
+            Ok(None) => {
+                let _ = invocation
+                    .say(ctx, "There's no data stored about you.")
+                    .await;
+                return;
+            }
+            Err(e) => {
+                error!("Error collecting user data: {:?}", e);
+                let _ = invocation
+                    .say(ctx, "Database error. Please try again later.")
+                    .await;
+                return;
+            }
+        };
+        let json = match data.to_json() {
+            Ok(json) => json,
+            Err(e) => {
+                error!("Error serializing user data: {:?}", e);
+                let _ = invocation
+                    .say(ctx, "Failed to build your export. Please try again later.")
+                    .await;
+                return;
+            }
+        };
+
+        let dm = CreateMessage::new()
+            .content("📦 Here's everything I have stored about you.")
+            .add_file(CreateAttachment::bytes(json.into_bytes(), "my_data.json"));
+        let message = match invocation.author.direct_message(&ctx.http, dm).await {
+            Ok(_) => "📬 Sent you a DM with your data.",
+            Err(e) => {
+                error!("Failed to DM data export: {:?}", e);
+                "I couldn't DM you. Please allow direct messages from server members and try again."
+            }
+        };
+        let _ = invocation.say(ctx, message).await;
+    }
+
+    /// Normalizes what the user pasted into a SteamID64, makes sure nobody else has linked
+    /// it and fetches the profile name. Tells the user what went wrong and returns `None`
+    /// on failure.
+    async fn resolve_profile(
+        &self,
+        ctx: &Context,
+        invocation: &Invocation,
+        steam_input: &str,
+    ) -> Option<(String, SteamProfile)> {
+        let steam_id = match self.steam.resolve_steam_id(steam_input).await {
+            Ok(id) => id,
+            Err(SteamError::NotFound) => {
+                let _ = invocation
+                    .say(
+                        ctx,
+                        format!(
+                            "Couldn't find a Steam profile for `{}`.\n{}",
+                            steam_input, LINK_STEAM_HELP
+                        ),
+                    )
+                    .await;
+                return None;
+            }
+            Err(err) => {
+                error!("Error resolving Steam ID {}: {:?}", steam_input, err);
+                let _ = invocation.say(ctx, steam_error_message(&err)).await;
+                return None;
+            }
+        };
+
+        let current = db::get_steam_id(&self.database, invocation.author.id.get() as i64).await;
+        match db::check_if_user_exists(&self.database, &steam_id).await {
+            Ok(true) if !matches!(&current, Ok(Some(id)) if *id == steam_id) => {
+                let _ = invocation
+                    .say(
+                        ctx,
+                        "That Steam account is already linked to another Discord account.",
+                    )
+                    .await;
+                return None;
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("Error checking if user exists: {:?}", e);
+                let _ = invocation
+                    .say(
+                        ctx,
+                        "⚠️ Error checking your Steam ID in the database. Try again later.",
+                    )
+                    .await;
+                return None;
+            }
+        }
+
+        // Attempt to fetch the Steam profile to validate the provided Steam ID.
+        match self.steam.fetch_profile(&steam_id).await {
+            Ok(profile) => Some((steam_id, profile)),
+            Err(err) => {
+                error!("Error fetching Steam profile: {:?}", err);
+                let _ = invocation.say(ctx, steam_error_message(&err)).await;
+                None
+            }
+        }
+    }
+
+    /// Shows `prompt` with Yes/No buttons and waits 30 seconds for the author to press one.
+    /// Returns `None` when nobody answered.
+    async fn confirm(
+        &self,
+        ctx: &Context,
+        invocation: &Invocation,
+        id: &str,
+        prompt: String,
+    ) -> Option<bool> {
+        let yes = format!("{}_yes", id);
+        let buttons = vec![CreateActionRow::Buttons(vec![
+            CreateButton::new(yes.clone())
+                .label("Yes")
+                .style(ButtonStyle::Success),
+            CreateButton::new(format!("{}_no", id))
+                .label("No")
+                .style(ButtonStyle::Danger),
+        ])];
+
+        let confirmation = match invocation
+            .send(ctx, Reply::new(prompt).components(buttons))
+            .await
+        {
+            Ok(message) => message,
+            Err(err) => {
+                eprintln!("Error sending confirmation message: {:?}", err);
+                return None;
+            }
+        };
+
+        // Buttons work for slash commands too, where message content isn't readable
+        let press = ComponentInteractionCollector::new(ctx)
+            .message_id(confirmation.id)
+            .author_id(invocation.author.id)
+            .timeout(Duration::from_secs(30))
+            .next()
+            .await?;
+
+        // Remove the buttons so the prompt can't be answered twice
+        let _ = press
+            .create_response(
+                &ctx.http,
+                CreateInteractionResponse::UpdateMessage(
+                    CreateInteractionResponseMessage::new().components(vec![]),
+                ),
+            )
+            .await;
+        Some(press.data.custom_id == yes)
+    }
+
+    /// Fetches a freshly linked account's library from Steam and stores it
+    async fn store_linked_games(&self, ctx: &Context, invocation: &Invocation, steam_id: &str) {
+        match self.steam.fetch_games(steam_id).await {
+            Ok(games_vector) if games_vector.is_empty() => {
+                let message = format!(
+                    "Your Steam library is empty. Once you own some games, they'll show up \
+                     after the next daily sync, or right away with {}.",
+                    invocation.command("sync", "sync")
+                );
+                let _ = invocation.say(ctx, message).await;
+            }
+            Ok(games_vector) => {
+                let steam_owned_games = crate::steam::SteamOwnedGames {
+                    games: games_vector,
+                };
+                let stored =
+                    db::store_steam_games(&self.database, steam_id, steam_owned_games).await;
+                self.caches.invalidate_user(steam_id).await;
+                if let Err(e) = stored {
+                    error!("Failed to store games in database: {:?}", e);
+                    let _ = invocation
+                        .say(ctx, "Error storing games in the database.")
+                        .await;
+                } else {
+                    let _ = invocation
+                        .say(ctx, "✅ Steam games successfully updated in the database!")
+                        .await;
+                }
+            }
+            Err(e) => {
+                error!("Failed to fetch games from Steam API: {:?}", e);
+                let _ = invocation.say(ctx, steam_error_message(&e)).await;
+            }
+        }
+    }
+
+    /// Handles `!sync`: refreshes the caller's library from Steam right away and reports
+    /// what changed since the last sync
+    pub async fn sync_library(&self, ctx: &Context, invocation: &Invocation) {
+        let discord_id = invocation.author.id.get() as i64;
+        let steam_id = match db::get_steam_id(&self.database, discord_id).await {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                let _ = invocation.say(ctx, not_linked_message(invocation)).await;
+                return;
+            }
+            Err(e) => {
+                error!("Error retrieving Steam ID: {:?}", e);
+                let _ = invocation
+                    .say(ctx, "Database error. Please try again later.")
+                    .await;
+                return;
+            }
+        };
+
+        let reply = match self.manual_sync(discord_id, &steam_id).await {
+            Ok(changes) => format!("✅ Library synced. {}", changes.summary()),
+            Err(message) => message,
+        };
+        let _ = invocation.say(ctx, reply).await;
+    }
+
+    /// Refreshes a user's library from Steam, at most once per [`SYNC_COOLDOWN`]. The
+    /// cooldown only sticks when the refresh succeeds, so a Steam outage or a private profile
+    /// doesn't stop the user from trying again. Errors are the reply to show.
+    pub async fn manual_sync(
+        &self,
+        discord_id: i64,
+        steam_id: &str,
+    ) -> Result<SyncChanges, String> {
+        match db::claim_manual_sync(&self.database, discord_id, SYNC_COOLDOWN).await {
+            Ok(None) => {}
+            Ok(Some(remaining)) => {
+                let minutes = remaining.as_secs().div_ceil(60).max(1);
+                return Err(format!(
+                    "⏳ You synced recently. Try again in {} minute{}.",
+                    minutes,
+                    if minutes == 1 { "" } else { "s" }
+                ));
+            }
+            Err(e) => {
+                error!("Error claiming sync cooldown for {}: {:?}", discord_id, e);
+                return Err("Database error. Please try again later.".to_string());
+            }
+        }
+
+        let result = self.refresh_for_sync(steam_id).await;
+        if result.is_err() {
+            if let Err(e) = db::release_manual_sync(&self.database, discord_id).await {
+                error!("Error releasing sync cooldown for {}: {:?}", discord_id, e);
+            }
+        }
+        result
+    }
+
+    /// The fetch and store behind [`Bot::manual_sync`]
+    async fn refresh_for_sync(&self, steam_id: &str) -> Result<SyncChanges, String> {
+        let before = match self.stored_library(steam_id).await {
+            Ok(stored) => stored.games,
+            Err(e) => {
+                error!("Error fetching stored games for {}: {:?}", steam_id, e);
+                return Err("Database error. Please try again later.".to_string());
+            }
+        };
+        let games = match self.steam.fetch_games(steam_id).await {
+            Ok(games) => games,
+            Err(e) => {
+                error!("Failed to fetch games for {}: {:?}", steam_id, e);
+                return Err(steam_error_message(&e));
+            }
+        };
+
+        let changes = SyncChanges::between(&before, &games);
+        let owned_games = crate::steam::SteamOwnedGames { games };
+        let stored = db::store_steam_games(&self.database, steam_id, owned_games).await;
+        self.caches.invalidate_user(steam_id).await;
+        if let Err(e) = stored {
+            error!("Failed to store games in database: {:?}", e);
+            return Err("Error storing games in the database.".to_string());
+        }
+        if let Err(e) = db::record_sync_success(&self.database, None, steam_id).await {
+            error!("Failed to record sync status for {}: {:?}", steam_id, e);
+        }
+
+        Ok(changes)
+    }
+
+    /// Displays the user's top `count` games by playtime.
+    pub async fn display_top_games(&self, ctx: &Context, invocation: &Invocation, count: usize) {
+        let discord_id = invocation.author.id.get() as i64;
+        let steam_id = match db::get_steam_id(&self.database, discord_id).await {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                let _ = invocation.say(ctx, not_linked_message(invocation)).await;
+                return;
+            }
+            Err(e) => {
+                error!("Error retrieving Steam ID: {:?}", e);
+                let _ = invocation
+                    .say(ctx, "Database error. Please try again later.")
+                    .await;
+                return;
+            }
+        };
+
+        if let Some(library) = self.get_steam_games(&steam_id).await {
+            // Recent playtime comes from the sync history; a missing history just hides it
+            let now = Utc::now();
+            let week_ago = now - chrono::Duration::days(7);
+            let recent: HashMap<u32, i64> = match db::get_minutes_played_by_game(
+                &self.database,
+                &steam_id,
+                week_ago,
+                now,
+            )
+            .await
+            {
+                Ok(rows) => rows
+                    .into_iter()
+                    .map(|(appid, _, minutes)| (appid, minutes))
+                    .collect(),
+                Err(e) => {
+                    error!("Error fetching recent playtime: {:?}", e);
+                    HashMap::new()
+                }
+            };
+
+            let mut sorted_games = library.games.clone();
+            sorted_games.sort_by_key(|g| std::cmp::Reverse(g.playtime_forever));
+            let top_games: Vec<String> = sorted_games
+                .iter()
+                .take(count)
+                .map(|game| match recent.get(&game.appid) {
+                    Some(minutes) if *minutes >= 60 => format!(
+                        "**{}** ({} hours, +{}h this week)",
+                        game.name,
+                        game.playtime_forever / 60,
+                        minutes / 60
+                    ),
+                    _ => format!("**{}** ({} hours)", game.name, game.playtime_forever / 60),
+                })
+                .collect();
+            let mut response_message = format!(
+                "Your top {} played games:\n{}",
+                top_games.len(),
+                top_games.join("\n")
+            );
+            if let Ok(minutes) =
+                db::get_minutes_played(&self.database, &steam_id, week_ago, now).await
+            {
+                if minutes > 0 {
+                    response_message.push_str(&format!(
+                        "\n\nYou played {:.1} hours in the last 7 days.",
+                        minutes as f64 / 60.0
+                    ));
+                }
+            }
+            if let Some(footer) = library.footer() {
+                response_message.push_str(&format!("\n\n-# {}", footer));
+            }
+            let _ = invocation.say(ctx, response_message).await;
+        } else {
+            let _ = invocation.say(ctx, "Error retrieving Steam data.").await;
+        }
+    }
+
+    /// Handles `!stats`: personal library and playtime analytics
+    pub async fn display_stats(&self, ctx: &Context, invocation: &Invocation) {
+        let discord_id = invocation.author.id.get() as i64;
+        let steam_id = match db::get_steam_id(&self.database, discord_id).await {
+            Ok(Some(id)) => id,
+            Ok(None) => {
+                let _ = invocation.say(ctx, not_linked_message(invocation)).await;
+                return;
+            }
+            Err(e) => {
+                error!("Error retrieving Steam ID: {:?}", e);
+                let _ = invocation
+                    .say(ctx, "Database error. Please try again later.")
+                    .await;
+                return;
+            }
+        };
+
+        let Some(library) = self.get_steam_games(&steam_id).await else {
+            let _ = invocation.say(ctx, "Error retrieving Steam data.").await;
+            return;
+        };
+        let games = &library.games;
+
+        let appids: Vec<u32> = games.iter().map(|g| g.appid).collect();
+        let history = tokio::try_join!(
+            db::get_app_genres(&self.database, &appids),
+            db::get_last_sync_gains(&self.database, &steam_id),
+        );
+        let (genres, gains) = match history {
+            Ok(history) => history,
+            Err(e) => {
+                error!("Error fetching stats for {}: {:?}", steam_id, e);
+                let _ = invocation
+                    .say(ctx, "Database error. Please try again later.")
+                    .await;
+                return;
+            }
+        };
+
+        let stats = LibraryStats::new(games, &genres, &gains);
+        let mut embed = CreateEmbed::new()
+            .title(format!("📊 Stats for {}", invocation.author.name))
+            .fields(
+                stats
+                    .fields()
+                    .into_iter()
+                    .map(|(name, value)| (name, value, true)),
+            );
+        if let Some(footer) = library.footer() {
+            embed = embed.footer(CreateEmbedFooter::new(footer));
+        }
+        if let Err(e) = invocation.send(ctx, Reply::default().embed(embed)).await {
+            error!("Failed to send stats: {:?}", e);
+        }
+    }
+
+    /// Handles `!together @a @b [--ai]`: games the caller and the mentioned members all
+    /// own, ranked for the group, plus an optional AI pick that none of them own
+    pub async fn play_together(
+        &self,
+        ctx: &Context,
+        invocation: &Invocation,
+        members: Vec<User>,
+        ask_ai: bool,
+    ) {
+        let mut group = vec![invocation.author.clone()];
+        for member in members {
+            if !group.iter().any(|u| u.id == member.id) {
+                group.push(member);
+            }
+        }
+        if group.len() < 2 {
+            let message = format!(
+                "Mention who you're playing with, e.g. {}.",
+                invocation.command(
+                    "together @friend1 @friend2",
+                    "together member1:@friend1 member2:@friend2"
+                )
+            );
+            let _ = invocation.say(ctx, message).await;
+            return;
+        }
+        if group.len() > MAX_GROUP_MENTIONS + 1 {
+            let _ = invocation
+                .say(
+                    ctx,
+                    format!(
+                        "That's a big group! Mention at most {} friends.",
+                        MAX_GROUP_MENTIONS
+                    ),
+                )
+                .await;
+            return;
+        }
+
+        let mut steam_ids = Vec::new();
+        let mut unlinked = Vec::new();
+        for user in &group {
+            match db::get_steam_id(&self.database, user.id.get() as i64).await {
+                Ok(Some(steam_id)) => steam_ids.push(steam_id),
+                Ok(None) => unlinked.push(user.to_string()),
+                Err(e) => {
+                    error!("Error retrieving Steam ID: {:?}", e);
+                    let _ = invocation
+                        .say(ctx, "Database error. Please try again later.")
+                        .await;
+                    return;
+                }
+            }
+        }
+        if !unlinked.is_empty() {
+            let _ = invocation
+                .say(
+                    ctx,
+                    format!(
+                        "These members need to link Steam first with {}: {}",
+                        invocation.command("link_steam", "link_steam"),
+                        unlinked.join(", ")
+                    ),
+                )
+                .await;
+            return;
+        }
+
+        let mut libraries = Vec::new();
+        for steam_id in &steam_ids {
+            match self.stored_library(steam_id).await {
+                Ok(stored) => libraries.push(stored.games),
+                Err(e) => {
+                    error!("Error fetching games for {}: {:?}", steam_id, e);
+                    let _ = invocation
+                        .say(ctx, "Database error. Please try again later.")
+                        .await;
+                    return;
+                }
+            }
+        }
+
+        let appids: Vec<u32> = libraries[0].iter().map(|g| g.appid).collect();
+        let categories = match db::get_app_categories(&self.database, &appids).await {
+            Ok(categories) => categories,
+            Err(e) => {
+                error!("Error fetching store categories: {:?}", e);
+                HashMap::new()
+            }
+        };
+        let shared = rank_shared_games(&libraries, &categories);
+
+        let description = if shared.is_empty() {
+            "You don't own any games in common yet.".to_string()
+        } else {
+            shared
+                .iter()
+                .take(SHARED_GAMES_SHOWN)
+                .enumerate()
+                .map(|(i, game)| {
+                    format!(
+                        "{}. **{}**{} — {}",
+                        i + 1,
+                        game.name,
+                        if game.multiplayer == Some(true) {
+                            " 👥"
+                        } else {
+                            ""
+                        },
+                        game.minutes
+                            .iter()
+                            .map(|m| format_hours(*m as u64))
+                            .collect::<Vec<_>>()
+                            .join(" · ")
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        let mut embed = CreateEmbed::new()
+            .title("🎮 What should we play together?")
+            .description(description)
+            .field(
+                "Playtime order",
+                group
+                    .iter()
+                    .map(|u| u.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" · "),
+                false,
+            )
+            .footer(CreateEmbedFooter::new(
+                "👥 = multiplayer on Steam. Games everyone plays a lot rank highest.",
+            ));
+
+        if ask_ai {
+            match self.claim_llm_quota(invocation).await {
+                Ok(Ok(usage_id)) => {
+                    let usage = TokenUsage::default();
+                    let pick = self
+                        .llm_client
+                        .get_group_recommendation(&self.database, &steam_ids, &usage)
+                        .await;
+                    self.settle_llm_quota(usage_id, &usage).await;
+                    match pick {
+                        Ok(Some(pick)) => {
+                            let value = match pick.steam_appid {
+                                Some(appid) => format!(
+                                    "**{}** — {}\n[Store page](https://store.steampowered.com/app/{}/)",
+                                    pick.title, pick.reason, appid
+                                ),
+                                None => format!("**{}** — {}", pick.title, pick.reason),
+                            };
+                            embed = embed.field("🤖 Something none of you own", value, false);
+                        }
+                        Ok(None) => {}
+                        Err(e) => error!("Error generating group recommendation: {:?}", e),
+                    }
+                }
+                Ok(Err(exceeded)) => {
+                    let message = exceeded.message(
+                        &invocation.command("recommend --engine cf", "recommend engine:cf"),
+                    );
+                    embed = embed.field("🤖 Something none of you own", message, false);
+                }
+                Err(e) => error!("Database error checking AI quota: {:?}", e),
+            }
+        }
+
+        if let Err(e) = invocation.send(ctx, Reply::default().embed(embed)).await {
+            error!("Failed to send group recommendation: {:?}", e);
+        }
+    }
+
+    /// Handles `!leaderboard [total|week|game <name>|hide|show]`
+    pub async fn show_leaderboard(
+        &self,
+        ctx: &Context,
+        invocation: &Invocation,
+        board: LeaderboardCommand,
+    ) {
+        let guild_id = invocation.guild_id.map_or(0, |id| id.get() as i64);
+        let (title, entries) = match board {
+            LeaderboardCommand::Hide | LeaderboardCommand::Show => {
+                let hide = board == LeaderboardCommand::Hide;
+                let discord_id = invocation.author.id.get() as i64;
+                let message =
+                    match db::set_leaderboard_opt_out(&self.database, discord_id, hide).await {
+                        Ok(true) if hide => "🙈 You're now hidden from leaderboards.".to_string(),
+                        Ok(true) => "👀 You'll now appear on leaderboards.".to_string(),
+                        Ok(false) => not_linked_message(invocation),
+                        Err(e) => {
+                            error!("Error updating leaderboard privacy: {:?}", e);
+                            "Database error. Please try again later.".to_string()
+                        }
+                    };
+                let _ = invocation.say(ctx, message).await;
+                return;
+            }
+            _ if invocation.guild_id.is_none() => {
+                let _ = invocation
+                    .say(ctx, "Leaderboards only work in a server.")
+                    .await;
+                return;
+            }
+            LeaderboardCommand::Total => (
+                "🏆 Total playtime".to_string(),
+                db::get_total_playtime_leaderboard(&self.database, guild_id).await,
+            ),
+            LeaderboardCommand::Week => {
+                let now = Utc::now();
+                (
+                    "🔥 Hours played in the last 7 days".to_string(),
+                    db::get_playtime_gained_leaderboard(
+                        &self.database,
+                        guild_id,
+                        now - chrono::Duration::days(7),
+                        now,
+                    )
+                    .await,
+                )
+            }
+            LeaderboardCommand::Game(query) => {
+                let games = match db::get_owned_game_names(&self.database, guild_id).await {
+                    Ok(games) => games,
+                    Err(e) => {
+                        error!("Error loading game names: {:?}", e);
+                        let _ = invocation
+                            .say(ctx, "Database error. Please try again later.")
+                            .await;
+                        return;
+                    }
+                };
+                let Some((appid, name, _)) = find_game(&query, &games) else {
+                    let _ = invocation
+                        .say(
+                            ctx,
+                            format!("No linked member owns a game matching `{}`.", query),
+                        )
+                        .await;
+                    return;
+                };
+                (
+                    format!("🎮 {}", name),
+                    db::get_game_playtime_leaderboard(&self.database, guild_id, *appid).await,
+                )
+            }
+        };
+
+        let entries = match entries {
+            Ok(entries) if entries.is_empty() => {
+                let _ = invocation
+                    .say(ctx, "Nobody has any playtime on this leaderboard yet.")
+                    .await;
+                return;
+            }
+            Ok(entries) => entries,
+            Err(e) => {
+                error!("Error building leaderboard: {:?}", e);
+                let _ = invocation
+                    .say(ctx, "Database error. Please try again later.")
+                    .await;
+                return;
+            }
+        };
+
+        let pages = page_count(entries.len());
+        let mut page = 0;
+        let opt_out = invocation.command("leaderboard hide", "leaderboard_privacy visible:False");
+        let reply = Reply::default()
+            .embed(leaderboard_embed(&title, &entries, page, &opt_out))
+            .components(leaderboard_buttons(page, pages));
+        let mut message = match invocation.send(ctx, reply).await {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Failed to send leaderboard: {:?}", e);
+                return;
+            }
+        };
+        if pages == 1 {
+            return;
+        }
+
+        // Anyone in the channel can page through; the buttons stop working after a quiet spell
+        while let Some(press) = ComponentInteractionCollector::new(ctx)
+            .message_id(message.id)
+            .timeout(LEADERBOARD_TIMEOUT)
+            .next()
+            .await
+        {
+            page = match press.data.custom_id.as_str() {
+                "leaderboard_prev" => page.saturating_sub(1),
+                "leaderboard_next" => (page + 1).min(pages - 1),
+                _ => page,
+            };
+            let update = CreateInteractionResponseMessage::new()
+                .embed(leaderboard_embed(&title, &entries, page, &opt_out))
+                .components(leaderboard_buttons(page, pages));
+            if let Err(e) = press
+                .create_response(&ctx.http, CreateInteractionResponse::UpdateMessage(update))
+                .await
+            {
+                error!("Failed to turn leaderboard page: {:?}", e);
+            }
+        }
+
+        if let Err(e) = message
+            .edit(ctx, EditMessage::new().components(vec![]))
+            .await
+        {
+            error!("Failed to remove leaderboard buttons: {:?}", e);
+        }
+    }
+
+    /// Get recommendations based on game history, from the LLM (optionally steered by a
+    /// free-text query such as "chill roguelike") or the offline collaborative filter
+    pub async fn recommend_games(
+        &self,
+        ctx: &Context,
+        invocation: &Invocation,
+        options: RecommendOptions,
+    ) {
+        let discord_id = invocation.author.id.get() as i64;
+
+        let intent = sanitize_intent(&options.query);
+        if !options.query.trim().is_empty() && intent.is_none() {
+            let message = format!(
+                "⚠️ I couldn't use that request. Describe what you're in the mood for, e.g. {}.",
+                invocation.command(
+                    "recommend chill co-op roguelike for short sessions",
+                    "recommend query:chill co-op roguelike for short sessions"
+                )
+            );
+            let _ = invocation.say(ctx, message).await;
+            return;
+        }
+
+        let steam_id = match db::get_steam_id(&self.database, discord_id).await {
+            Ok(Some(steam_id)) => steam_id,
+            Ok(None) => {
+                let _ = invocation.say(ctx, not_linked_message(invocation)).await;
+                return;
+            }
+            Err(e) => {
+                error!("Database error retrieving Steam ID: {:?}", e);
+                let _ = invocation
+                    .say(ctx, "⚠️ Database error. Please try again later.")
+                    .await;
+                return;
+            }
+        };
+
+        let genre = match &options.genre {
+            Some(input) => match GenreFilter::load(&self.database, input, &self.store).await {
+                Ok(Ok(filter)) => Some(filter),
+                Ok(Err(suggestions)) => {
+                    let message = if suggestions.is_empty() {
+                        format!(
+                            "⚠️ I don't know the genre `{}` yet. Genres become available \
+                             once linked libraries have synced.",
+                            input
+                        )
+                    } else {
+                        format!(
+                            "⚠️ I don't know the genre `{}`. Did you mean: {}?",
+                            input,
+                            suggestions
+                                .iter()
+                                .map(|s| format!("`{}`", s))
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    };
+                    let _ = invocation.say(ctx, message).await;
+                    return;
+                }
+                Err(e) => {
+                    error!("Database error loading genres: {:?}", e);
+                    let _ = invocation
+                        .say(ctx, "⚠️ Database error. Please try again later.")
+                        .await;
+                    return;
+                }
+            },
+            None => None,
+        };
+
+        if let Err(e) = invocation
+            .say(
+                ctx,
+                format!("🔍 Getting recommendations for {}...", invocation.author),
+            )
+            .await
+        {
+            error!("Failed to send message: {:?}", e);
+            return;
+        }
+
+        let settings = match invocation.guild_id {
+            Some(guild_id) => Some(self.settings_for(guild_id).await),
+            None => None,
+        };
+        let request = RecommendationRequest {
+            intent: intent.as_deref(),
+            genre: genre.as_ref(),
+            guild_id: settings.as_ref().map(|s| s.guild_id),
+            persona: settings.as_ref().is_none_or(|s| s.llm_persona),
+        };
+
+        // Fetch recommendations
+        let result = match options.engine {
+            Engine::Cf => {
+                // Rank everything, then keep the best games known to have the genre
+                let limit = if genre.is_some() {
+                    usize::MAX
+                } else {
+                    CF_RECOMMENDATIONS
+                };
+                collaborative::recommend_for_user(
+                    &self.database,
+                    &steam_id,
+                    request.guild_id,
+                    limit,
+                )
+                .await
+                .map(|recs| {
+                    recs.into_iter()
+                        .filter(|rec| {
+                            genre
+                                .as_ref()
+                                .is_none_or(|g| g.contains(rec.appid) == Some(true))
+                        })
+                        .take(CF_RECOMMENDATIONS)
+                        .map(Recommendation::from)
+                        .collect()
+                })
+                .map(Ok)
+                .map_err(|e| e.to_string())
+            }
+            engine => {
+                self.llm_recommendations(invocation, &steam_id, engine, request)
+                    .await
+            }
+        };
+
+        match result {
+            Ok(Err(exceeded)) => {
+                let cf = invocation.command("recommend --engine cf", "recommend engine:cf");
+                let _ = invocation.say(ctx, exceeded.message(&cf)).await;
+            }
+            Ok(Ok(recommendations)) if recommendations.is_empty() => {
+                let _ = invocation
+                    .say(
+                        ctx,
+                        "⚠️ There isn't enough data to recommend real games yet. Make sure your \
+                         library has synced, or try again once more members have linked their \
+                         Steam accounts.",
+                    )
+                    .await;
+            }
+            Ok(Ok(recommendations)) => {
+                let reply = Reply::default().embed(recommendation_embed(&recommendations));
+                if let Err(e) = invocation.send(ctx, reply).await {
+                    error!("Failed to send recommendation: {:?}", e);
+                }
+            }
+            Err(e) => {
+                error!("Error generating recommendations: {:?}", e);
+                let _ = invocation
+                    .say(
+                        ctx,
+                        format!("⚠️ Error generating recommendations:\n```{}```", e),
+                    )
+                    .await;
+            }
+        }
+    }
+
+    /// Recommendations from the LLM-backed engines. Identical requests within a minute share
+    /// one response, which doesn't count against the caller's AI quota. An empty list means
+    /// the hybrid engine had no real games to choose from.
+    async fn llm_recommendations(
+        &self,
+        invocation: &Invocation,
+        steam_id: &str,
+        engine: Engine,
+        request: RecommendationRequest<'_>,
+    ) -> Result<Result<Vec<Recommendation>, QuotaExceeded>, String> {
+        let key = RecommendationKey {
+            steam_id: steam_id.to_string(),
+            engine: match engine {
+                Engine::Hybrid => "hybrid",
+                Engine::Llm => "llm",
+                Engine::Cf => "cf",
+            },
+            intent: request.intent.map(str::to_string),
+            genre: request.genre.map(|g| g.genre.clone()),
+            guild_id: request.guild_id,
+            persona: request.persona,
+        };
+        // Concurrent identical requests wait for the first one instead of calling the
+        // provider (and using up quota) themselves
+        let loaded = self
+            .caches
+            .recommendations
+            .get_or_try_insert(key, async {
+                let usage_id = match self.claim_llm_quota(invocation).await {
+                    Ok(Ok(usage_id)) => usage_id,
+                    Ok(Err(exceeded)) => return Err(LlmFailure::Quota(exceeded)),
+                    Err(e) => return Err(LlmFailure::Failed(e.to_string())),
+                };
+
+                let usage = TokenUsage::default();
+                let result = match engine {
+                    // No candidates at all means nothing real to ground picks in, so the
+                    // reply says there isn't enough data rather than falling back to the
+                    // model's own picks
+                    Engine::Hybrid => self
+                        .llm_client
+                        .get_hybrid_recommendation(&self.database, steam_id, request, &usage)
+                        .await
+                        .map(Option::unwrap_or_default),
+                    _ => {
+                        self.llm_client
+                            .get_recommendation(&self.database, steam_id, request, &usage)
+                            .await
+                    }
+                };
+                self.settle_llm_quota(usage_id, &usage).await;
+                result.map_err(|e| LlmFailure::Failed(e.to_string()))
+            })
+            .await;
+
+        match loaded {
+            Ok(recommendations) => Ok(Ok(recommendations)),
+            Err(failure) => match &*failure {
+                LlmFailure::Quota(exceeded) => Ok(Err(*exceeded)),
+                LlmFailure::Failed(e) => Err(e.clone()),
+            },
+        }
+    }
+
+    /// Counts one AI request against the caller's and their guild's limits, unless it would
+    /// go over one of them, and returns the id of the recorded use
+    async fn claim_llm_quota(
+        &self,
+        invocation: &Invocation,
+    ) -> Result<Result<i64, QuotaExceeded>, sqlx::Error> {
+        let settings = match invocation.guild_id {
+            Some(guild_id) => Some(self.settings_for(guild_id).await),
+            None => None,
+        };
+        db::claim_llm_quota(
+            &self.database,
+            invocation.author.id.get() as i64,
+            settings.as_ref().map(|s| s.guild_id),
+            &self.llm_limits.for_guild(settings.as_ref()),
+            ESTIMATED_TOKENS_PER_REQUEST,
+        )
+        .await
+    }
+
+    /// Records what a claimed AI request really cost. A request that never got an answer
+    /// from the provider is given back, so errors don't use up the caller's cooldown or
+    /// limits.
+    async fn settle_llm_quota(&self, usage_id: i64, usage: &TokenUsage) {
+        let settled = if usage.is_empty() {
+            db::refund_llm_quota(&self.database, usage_id).await
+        } else {
+            db::settle_llm_usage(&self.database, usage_id, usage.total()).await
+        };
+        if let Err(e) = settled {
+            error!("Failed to settle AI usage {}: {:?}", usage_id, e);
+        }
+    }
+}
+
+/// One page of a leaderboard as an embed, whose footer names the `opt_out` command
+fn leaderboard_embed(
+    title: &str,
+    entries: &[LeaderboardEntry],
+    page: usize,
+    opt_out: &str,
+) -> CreateEmbed {
+    CreateEmbed::new()
+        .title(title)
+        .description(render_page(entries, page))
+        .footer(CreateEmbedFooter::new(format!(
+            "Page {}/{} · Use {} to opt out",
+            page + 1,
+            page_count(entries.len()),
+            opt_out
+        )))
+}
+
+/// The reply for commands that need a linked Steam account
+fn not_linked_message(invocation: &Invocation) -> String {
+    format!(
+        "You haven't linked your Steam ID yet! Use {}.",
+        invocation.command("link_steam <profile>", "link_steam")
+    )
+}
+
+/// Previous/next buttons, disabled at either end
+fn leaderboard_buttons(page: usize, pages: usize) -> Vec<CreateActionRow> {
+    if pages <= 1 {
+        return Vec::new();
+    }
+    vec![CreateActionRow::Buttons(vec![
+        CreateButton::new("leaderboard_prev")
+            .label("◀")
+            .style(ButtonStyle::Secondary)
+            .disabled(page == 0),
+        CreateButton::new("leaderboard_next")
+            .label("▶")
+            .style(ButtonStyle::Secondary)
+            .disabled(page + 1 >= pages),
+    ])]
+}
+
+/// Renders recommendations as one embed field per game, linking the store page when known
+fn recommendation_embed(recommendations: &[Recommendation]) -> CreateEmbed {
+    let fields = recommendations.iter().enumerate().map(|(i, rec)| {
+        // Titles and reasons can come from the model, so cut them down to what Discord
+        // accepts, keeping the store link whole
+        let link = match rec.steam_appid {
+            Some(appid) => format!(
+                "\n[Store page](https://store.steampowered.com/app/{}/)",
+                appid
+            ),
+            None => String::new(),
+        };
+        let reason = truncate_chars(&rec.reason, EMBED_FIELD_VALUE_LIMIT - link.chars().count());
+        let name = truncate_chars(&format!("{}. {}", i + 1, rec.title), EMBED_FIELD_NAME_LIMIT);
+        (name, reason + &link, false)
+    });
+
+    CreateEmbed::new()
+        .title("🎮 Based on your game history, you might enjoy:")
+        .fields(fields)
+}
+
+/// Shortens `text` to at most `max` characters, ending with an ellipsis when cut
+pub fn truncate_chars(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut truncated: String = text.chars().take(max.saturating_sub(1)).collect();
+    truncated.push('…');
+    truncated
+}
+
+impl From<CfRecommendation> for Recommendation {
+    fn from(rec: CfRecommendation) -> Self {
+        let reason = match rec.players {
+            1 => "Played heavily by a server member with similar taste".to_string(),
+            n => format!("Played heavily by {} server members with similar taste", n),
+        };
+        Recommendation {
+            title: rec.name,
+            reason,
+            steam_appid: Some(rec.appid),
+        }
+    }
+}
+
+/// What the user can do about a failed Steam call
+fn steam_error_message(error: &SteamError) -> String {
+    match error {
+        SteamError::PrivateProfile => PRIVATE_PROFILE_HELP.to_string(),
+        SteamError::NotFound => format!(
+            "Couldn't find that Steam profile. Please check it and try again.\n{}",
+            LINK_STEAM_HELP
+        ),
+        SteamError::RateLimited => {
+            "⏳ Steam is limiting how often I can ask right now. Please try again in a few minutes."
+                .to_string()
+        }
+        SteamError::Unauthorized => {
+            "⚠️ Steam rejected my API key, so I can't reach Steam at the moment. Please let the \
+             bot's owner know."
+                .to_string()
+        }
+        SteamError::Upstream(_) => {
+            "⚠️ Steam isn't responding properly right now. Please try again later.".to_string()
+        }
+    }
+}
+
+/// An extra confirmation line when the profile is hidden, since its games can't be synced
+fn private_profile_warning(profile: &SteamProfile) -> &'static str {
+    if profile.is_public() {
+        ""
+    } else {
+        "⚠️ This profile is private, so your games can't be synced until you make your profile \
+         and game details public.\n"
+    }
+}
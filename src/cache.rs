@@ -0,0 +1,160 @@
+use crate::llm::Recommendation;
+use crate::steam::SteamGame;
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::future::Future;
+use std::hash::Hash;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How many libraries stay in memory, and for how long
+const LIBRARY_CAPACITY: u64 = 1_000;
+const LIBRARY_TTL: Duration = Duration::from_secs(10 * 60);
+
+/// Identical `!recommend` calls within this window share one LLM response
+const RECOMMENDATION_CAPACITY: u64 = 500;
+const RECOMMENDATION_TTL: Duration = Duration::from_secs(60);
+
+/// A bounded in-memory cache whose entries expire `ttl` after being written, counting hits
+/// and misses for monitoring
+pub struct Cache<K, V> {
+    entries: moka::future::Cache<K, V>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl<K, V> Cache<K, V>
+where
+    K: Hash + Eq + Send + Sync + 'static,
+    V: Clone + Send + Sync + 'static,
+{
+    pub fn new(capacity: u64, ttl: Duration) -> Self {
+        Cache {
+            entries: moka::future::Cache::builder()
+                .max_capacity(capacity)
+                .time_to_live(ttl)
+                .support_invalidation_closures()
+                .build(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    pub async fn get(&self, key: &K) -> Option<V> {
+        let value = self.entries.get(key).await;
+        let counter = if value.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        value
+    }
+
+    /// Returns the cached value, or loads and caches it. Concurrent calls for the same
+    /// missing key share one load, and its error if it fails. Failed loads aren't cached.
+    pub async fn get_or_try_insert<E, F>(&self, key: K, load: F) -> Result<V, Arc<E>>
+    where
+        F: Future<Output = Result<V, E>>,
+        E: Send + Sync + 'static,
+    {
+        if let Some(value) = self.get(&key).await {
+            return Ok(value);
+        }
+        let entry = self.entries.entry(key).or_try_insert_with(load).await?;
+        Ok(entry.into_value())
+    }
+
+    pub async fn invalidate(&self, key: &K) {
+        self.entries.invalidate(key).await;
+    }
+
+    /// Drops every entry whose key matches `predicate`. Matching entries are removed lazily,
+    /// but are never returned again.
+    pub fn invalidate_if(&self, predicate: impl Fn(&K) -> bool + Send + Sync + 'static) {
+        // Only fails when the builder didn't enable invalidation closures, which `new` does
+        let _ = self
+            .entries
+            .invalidate_entries_if(move |key, _| predicate(key));
+    }
+
+    pub fn stats(&self) -> CacheStats {
+        CacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            entries: self.entries.entry_count(),
+        }
+    }
+}
+
+/// Counters of one cache since startup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    /// Approximate; expired and evicted entries are cleaned up in the background
+    pub entries: u64,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let lookups = self.hits + self.misses;
+        let hit_rate = if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 * 100.0 / lookups as f64
+        };
+        write!(
+            f,
+            "{} hits, {} misses ({:.1}% hit rate), {} entries",
+            self.hits, self.misses, hit_rate, self.entries
+        )
+    }
+}
+
+/// A user's stored library together with when a sync last wrote it
+#[derive(Debug, Clone, Default)]
+pub struct StoredLibrary {
+    pub games: Vec<SteamGame>,
+    pub synced_at: Option<DateTime<Utc>>,
+}
+
+/// Everything that shapes an LLM recommendation, so only identical requests share a response
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RecommendationKey {
+    pub steam_id: String,
+    /// Which recommender produced the response
+    pub engine: &'static str,
+    pub intent: Option<String>,
+    pub genre: Option<String>,
+    pub guild_id: Option<i64>,
+    pub persona: bool,
+}
+
+/// The caches shared by the bot and the scheduler
+pub struct Caches {
+    /// Stored libraries by steam id, in front of `db::get_user_games`
+    pub libraries: Cache<String, StoredLibrary>,
+    /// LLM recommendations, so a repeated request doesn't call the provider again
+    pub recommendations: Cache<RecommendationKey, Vec<Recommendation>>,
+}
+
+impl Default for Caches {
+    fn default() -> Self {
+        Caches {
+            libraries: Cache::new(LIBRARY_CAPACITY, LIBRARY_TTL),
+            recommendations: Cache::new(RECOMMENDATION_CAPACITY, RECOMMENDATION_TTL),
+        }
+    }
+}
+
+impl Caches {
+    /// Forgets everything cached for a Steam account, after a sync or unlink changed it
+    pub async fn invalidate_user(&self, steam_id: &str) {
+        self.libraries.invalidate(&steam_id.to_string()).await;
+        let steam_id = steam_id.to_string();
+        self.recommendations
+            .invalidate_if(move |key| key.steam_id == steam_id);
+    }
+}
@@ -0,0 +1,142 @@
+use crate::collaborative::CfModel;
+use crate::database::db::{get_all_app_tags, get_server_user_games};
+use crate::steam::SteamGame;
+use itertools::Itertools;
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+
+/// A real, unowned game the LLM is allowed to pick from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate {
+    pub appid: u32,
+    pub name: String,
+    pub score: f64,
+    /// Why the game was picked, passed to the LLM as grounding
+    pub evidence: String,
+}
+
+/// How many candidates the LLM gets to choose from
+pub const CANDIDATE_POOL_SIZE: usize = 30;
+
+/// `appid -> (store name, [(tag, votes)])`, as returned by `db::get_all_app_tags`
+pub type AppTags = HashMap<u32, (String, Vec<(String, u32)>)>;
+
+/// Builds the grounded candidate list for a user from local data only. Games that players
+/// with similar libraries play heavily are interleaved with games whose store tags match
+/// what the user plays, then topped up with the server's most-played games.
+/// Nothing the user owns is ever included. With a `guild_id`, only that server's members
+/// count as other players.
+pub async fn generate_candidates(
+    pool: &PgPool,
+    steam_id: &str,
+    guild_id: Option<i64>,
+    limit: usize,
+) -> Result<Vec<Candidate>, sqlx::Error> {
+    let rows = get_server_user_games(pool, guild_id).await?;
+    let app_tags = get_all_app_tags(pool).await?;
+    let model = CfModel::build(&rows);
+    let user_games: Vec<&SteamGame> = rows
+        .iter()
+        .filter(|(id, _)| id == steam_id)
+        .map(|(_, game)| game)
+        .collect();
+
+    let co_played = model
+        .recommend(steam_id, limit)
+        .into_iter()
+        .map(|rec| Candidate {
+            appid: rec.appid,
+            name: rec.name,
+            score: rec.score,
+            evidence: format!(
+                "played a lot by {} people with similar libraries",
+                rec.players
+            ),
+        });
+    let tag_matches = tag_similar(&app_tags, &user_games, limit);
+    let popular = model
+        .popular(steam_id, limit)
+        .into_iter()
+        .map(|rec| Candidate {
+            appid: rec.appid,
+            name: rec.name,
+            score: 0.0,
+            evidence: format!("popular on this server ({} players)", rec.players),
+        });
+
+    let mut seen = HashSet::new();
+    let candidates = co_played
+        .interleave(tag_matches)
+        .chain(popular)
+        .filter(|c| seen.insert(c.appid))
+        .take(limit)
+        .collect();
+
+    Ok(candidates)
+}
+
+/// Unowned apps whose store tags best match the user's playtime-weighted tag profile
+pub fn tag_similar(app_tags: &AppTags, user_games: &[&SteamGame], limit: usize) -> Vec<Candidate> {
+    let owned: HashSet<u32> = user_games.iter().map(|g| g.appid).collect();
+
+    // Tag weights are relative to the app's most voted tag so popular games don't dominate
+    let tag_vector = |tags: &[(String, u32)]| -> HashMap<String, f64> {
+        let max_votes = tags.iter().map(|(_, v)| *v).max().unwrap_or(0).max(1) as f64;
+        tags.iter()
+            .map(|(tag, votes)| (tag.clone(), *votes as f64 / max_votes))
+            .collect()
+    };
+
+    let mut profile: HashMap<String, f64> = HashMap::new();
+    for game in user_games.iter().filter(|g| g.playtime_forever > 0) {
+        if let Some((_, tags)) = app_tags.get(&game.appid) {
+            let weight = (1.0 + game.playtime_forever as f64 / 60.0).ln();
+            for (tag, tag_weight) in tag_vector(tags) {
+                *profile.entry(tag).or_default() += weight * tag_weight;
+            }
+        }
+    }
+    let profile_norm = profile.values().map(|w| w * w).sum::<f64>().sqrt();
+    if profile_norm == 0.0 {
+        return Vec::new();
+    }
+
+    let mut matches: Vec<Candidate> = app_tags
+        .iter()
+        .filter(|(appid, _)| !owned.contains(appid))
+        .filter_map(|(&appid, (name, tags))| {
+            let vector = tag_vector(tags);
+            let norm = vector.values().map(|w| w * w).sum::<f64>().sqrt();
+            let dot: f64 = vector
+                .iter()
+                .filter_map(|(tag, w)| profile.get(tag).map(|p| p * w))
+                .sum();
+            if dot <= 0.0 {
+                return None;
+            }
+
+            let shared = vector
+                .iter()
+                .filter_map(|(tag, w)| profile.get(tag).map(|p| (tag, p * w)))
+                .sorted_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(b.0)))
+                .take(3)
+                .map(|(tag, _)| tag.as_str())
+                .join(", ");
+
+            Some(Candidate {
+                appid,
+                name: name.clone(),
+                score: dot / (norm * profile_norm),
+                evidence: format!("shares the tags {} with games they play", shared),
+            })
+        })
+        .collect();
+
+    matches.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    matches.truncate(limit);
+    matches
+}
@@ -0,0 +1,161 @@
+use crate::database::db::get_server_user_games;
+use crate::steam::SteamGame;
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+
+/// A game suggested from what similar players spend their time on
+#[derive(Debug, Clone, PartialEq)]
+pub struct CfRecommendation {
+    pub appid: u32,
+    pub name: String,
+    pub score: f64,
+    /// How many other players who share the user's games also play this one
+    pub players: usize,
+}
+
+/// Item-item collaborative filter over every library in the `games` table.
+///
+/// Each played game is weighted by `ln(1 + hours)` so a 500 hour favourite counts more than
+/// a 2 hour try, without letting one obsessive player dominate. Games are compared by the
+/// cosine similarity of those weights across players, and candidates are scored by how
+/// similar they are to what the user already plays.
+pub struct CfModel {
+    names: HashMap<u32, String>,
+    /// steam_id -> appid -> weight, played games only
+    weights: HashMap<String, HashMap<u32, f64>>,
+    /// appid -> steam_ids that played it
+    players: HashMap<u32, Vec<String>>,
+    /// appid -> L2 norm of its weight vector
+    norms: HashMap<u32, f64>,
+    /// steam_id -> everything owned, played or not
+    owned: HashMap<String, HashSet<u32>>,
+}
+
+impl CfModel {
+    pub fn build(rows: &[(String, SteamGame)]) -> Self {
+        let mut names = HashMap::new();
+        let mut weights: HashMap<String, HashMap<u32, f64>> = HashMap::new();
+        let mut players: HashMap<u32, Vec<String>> = HashMap::new();
+        let mut norms: HashMap<u32, f64> = HashMap::new();
+        let mut owned: HashMap<String, HashSet<u32>> = HashMap::new();
+
+        for (steam_id, game) in rows {
+            names.entry(game.appid).or_insert_with(|| game.name.clone());
+            owned
+                .entry(steam_id.clone())
+                .or_default()
+                .insert(game.appid);
+
+            if game.playtime_forever == 0 {
+                continue;
+            }
+            let weight = (1.0 + game.playtime_forever as f64 / 60.0).ln();
+            weights
+                .entry(steam_id.clone())
+                .or_default()
+                .insert(game.appid, weight);
+            players
+                .entry(game.appid)
+                .or_default()
+                .push(steam_id.clone());
+            *norms.entry(game.appid).or_default() += weight * weight;
+        }
+
+        for norm in norms.values_mut() {
+            *norm = norm.sqrt();
+        }
+
+        CfModel {
+            names,
+            weights,
+            players,
+            norms,
+            owned,
+        }
+    }
+
+    /// Up to `limit` games the user doesn't own, best first
+    pub fn recommend(&self, steam_id: &str, limit: usize) -> Vec<CfRecommendation> {
+        let Some(user_weights) = self.weights.get(steam_id) else {
+            return Vec::new();
+        };
+        let owned = self.owned.get(steam_id);
+
+        // score(j) = sum over the user's games i of w_ui * cos(i, j). The cosine dot product
+        // only has terms for players who played both, so walk those players directly.
+        let mut scores: HashMap<u32, f64> = HashMap::new();
+        let mut supporters: HashMap<u32, HashSet<&str>> = HashMap::new();
+        for (&item, &user_weight) in user_weights {
+            let item_norm = self.norms[&item];
+            for other in &self.players[&item] {
+                if other == steam_id {
+                    continue;
+                }
+                let other_weights = &self.weights[other];
+                let other_item_weight = other_weights[&item];
+                for (&candidate, &candidate_weight) in other_weights {
+                    if owned.is_some_and(|o| o.contains(&candidate)) {
+                        continue;
+                    }
+                    let similarity_term =
+                        other_item_weight * candidate_weight / (item_norm * self.norms[&candidate]);
+                    *scores.entry(candidate).or_default() += user_weight * similarity_term;
+                    supporters.entry(candidate).or_default().insert(other);
+                }
+            }
+        }
+
+        let mut recommendations: Vec<CfRecommendation> = scores
+            .into_iter()
+            .map(|(appid, score)| CfRecommendation {
+                appid,
+                name: self.names[&appid].clone(),
+                score,
+                players: supporters.get(&appid).map_or(0, |s| s.len()),
+            })
+            .collect();
+        recommendations.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        recommendations.truncate(limit);
+        recommendations
+    }
+
+    /// Up to `limit` unowned games with the most playtime weight across the whole server,
+    /// for users whose games nobody else plays yet
+    pub fn popular(&self, steam_id: &str, limit: usize) -> Vec<CfRecommendation> {
+        let owned = self.owned.get(steam_id);
+        let mut popular: Vec<CfRecommendation> = self
+            .players
+            .iter()
+            .filter(|(appid, _)| !owned.is_some_and(|o| o.contains(appid)))
+            .map(|(&appid, players)| CfRecommendation {
+                appid,
+                name: self.names[&appid].clone(),
+                score: players.iter().map(|p| self.weights[p][&appid]).sum(),
+                players: players.len(),
+            })
+            .collect();
+        popular.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        popular.truncate(limit);
+        popular
+    }
+}
+
+/// Builds the model from every library in the server, or the whole database without a
+/// `guild_id`, and recommends for one user. Works entirely offline.
+pub async fn recommend_for_user(
+    pool: &PgPool,
+    steam_id: &str,
+    guild_id: Option<i64>,
+    limit: usize,
+) -> Result<Vec<CfRecommendation>, sqlx::Error> {
+    let rows = get_server_user_games(pool, guild_id).await?;
+    Ok(CfModel::build(&rows).recommend(steam_id, limit))
+}
@@ -1,6 +1,15 @@
+use crate::export::{PlaytimeRecord, UserData};
+use crate::guilds::GuildSettings;
+use crate::leaderboard::LeaderboardEntry;
+use crate::quota::{LlmLimits, QuotaExceeded, QuotaLimit};
 use crate::steam::SteamGame;
 use crate::steam::SteamOwnedGames;
-use sqlx::PgPool;
+use crate::store::StoreAppDetails;
+use chrono::{DateTime, Utc};
+use sqlx::pool::PoolConnection;
+use sqlx::{PgPool, Postgres};
+use std::collections::HashMap;
+use std::time::Duration;
 
 /// Links a user's steam account to their discord via database
 pub async fn link_steam(
@@ -34,41 +43,300 @@ pub async fn get_steam_id(pool: &PgPool, discord_id: i64) -> Result<Option<Strin
     Ok(steam_id.map(|record| record.steam_id))
 }
 
-/// Stores a user's steam games into the database
+/// Deletes a user and their server memberships in one transaction; their games and playtime
+/// history cascade with the `users` row. Returns the Steam ID that was unlinked, or `None`
+/// if the user had nothing linked.
+pub async fn unlink_steam(pool: &PgPool, discord_id: i64) -> Result<Option<String>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let Some(steam_id) = sqlx::query_scalar!(
+        "SELECT steam_id FROM users WHERE discord_id = $1 FOR UPDATE;",
+        discord_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    sqlx::query!(
+        "DELETE FROM guild_members WHERE discord_id = $1;",
+        discord_id
+    )
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query!("DELETE FROM users WHERE discord_id = $1;", discord_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Some(steam_id))
+}
+
+/// Points a linked user at a different Steam account and drops everything stored for the
+/// old one, in one transaction. The old rows have to go first, since they reference the
+/// `users` row being changed. Returns the previous Steam ID, or `None` if the user had
+/// nothing linked.
+pub async fn relink_steam(
+    pool: &PgPool,
+    discord_id: i64,
+    steam_id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let Some(previous) = sqlx::query_scalar!(
+        "SELECT steam_id FROM users WHERE discord_id = $1 FOR UPDATE;",
+        discord_id
+    )
+    .fetch_optional(&mut *tx)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    delete_steam_data(&mut tx, &previous).await?;
+    // The new account has never been synced, so the next read fetches it from Steam
+    sqlx::query!(
+        "UPDATE users SET steam_id = $2, last_synced_at = NULL WHERE discord_id = $1;",
+        discord_id,
+        steam_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(Some(previous))
+}
+
+/// Removes the synced library and playtime history of a Steam account
+async fn delete_steam_data(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    steam_id: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM games WHERE steam_id = $1;", steam_id)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query!(
+        "DELETE FROM playtime_snapshots WHERE steam_id = $1;",
+        steam_id
+    )
+    .execute(&mut **tx)
+    .await?;
+    sqlx::query!("DELETE FROM sync_status WHERE steam_id = $1;", steam_id)
+        .execute(&mut **tx)
+        .await?;
+
+    Ok(())
+}
+
+/// Collects everything stored about a Discord user, or `None` if they never linked
+pub async fn get_user_data(
+    pool: &PgPool,
+    discord_id: i64,
+) -> Result<Option<UserData>, sqlx::Error> {
+    let Some(user) = sqlx::query!(
+        "SELECT username, steam_id, leaderboard_opt_out FROM users WHERE discord_id = $1;",
+        discord_id
+    )
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let guild_ids = sqlx::query_scalar!(
+        "SELECT guild_id FROM guild_members WHERE discord_id = $1 ORDER BY guild_id;",
+        discord_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let history = sqlx::query!(
+        "SELECT appid, playtime_forever, delta_minutes, captured_at
+         FROM playtime_snapshots WHERE steam_id = $1
+         ORDER BY captured_at, appid;",
+        user.steam_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut games = get_user_games(pool, &user.steam_id).await?;
+    games.sort_by_key(|game| game.appid);
+
+    Ok(Some(UserData {
+        discord_id,
+        username: user.username,
+        steam_id: user.steam_id,
+        leaderboard_opt_out: user.leaderboard_opt_out,
+        guild_ids,
+        games,
+        playtime_history: history
+            .into_iter()
+            .map(|rec| PlaytimeRecord {
+                appid: rec.appid as u32,
+                playtime_forever: rec.playtime_forever as u32,
+                delta_minutes: rec.delta_minutes as u32,
+                captured_at: rec.captured_at.to_rfc3339(),
+            })
+            .collect(),
+    }))
+}
+
+/// Stores a user's steam games into the database. The Steam ID must belong to a linked user.
 pub async fn store_steam_games(
     pool: &PgPool,
     steam_id: &str,
     owned_games: SteamOwnedGames,
 ) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query!(
+        "UPDATE users SET last_synced_at = NOW() WHERE steam_id = $1;",
+        steam_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    // An empty VALUES list isn't valid SQL, and there are no games to record anyway
+    if owned_games.games.is_empty() {
+        tx.commit().await?;
+        return Ok(());
+    }
+
     // Build the query because it is FAR faster
     let mut query_builder = sqlx::QueryBuilder::new(
-        "INSERT INTO games (steam_id, name, playtime_forever, last_updated) ",
+        "INSERT INTO games (steam_id, appid, name, playtime_forever, img_icon_url, \
+         playtime_2weeks, rtime_last_played, last_updated) ",
     );
 
     query_builder.push_values(owned_games.games.iter(), |mut row_builder, game| {
         row_builder
             .push_bind(steam_id)
+            .push_bind(game.appid as i32)
             .push_bind(&game.name)
             .push_bind(game.playtime_forever as i32)
+            .push_bind(&game.img_icon_url)
+            .push_bind(game.playtime_2weeks.map(|p| p as i32))
+            .push_bind(game.rtime_last_played.map(i64::from))
             .push("NOW()");
     });
 
-    // Finish building the query after iterating through the games
+    // Finish building the query after iterating through the games.
+    // Keyed on appid so renamed titles update in place instead of duplicating.
     query_builder.push(
-        " ON CONFLICT (steam_id, name)
-          DO UPDATE SET playtime_forever = EXCLUDED.playtime_forever,
+        " ON CONFLICT (steam_id, appid)
+          DO UPDATE SET name = EXCLUDED.name,
+          playtime_forever = EXCLUDED.playtime_forever,
+          img_icon_url = EXCLUDED.img_icon_url,
+          playtime_2weeks = EXCLUDED.playtime_2weeks,
+          rtime_last_played = EXCLUDED.rtime_last_played,
           last_updated = NOW();",
     );
 
     let query = query_builder.build();
-    query.execute(pool).await?;
+    query.execute(&mut *tx).await?;
+
+    // Last recorded playtime per game, to turn lifetime totals into deltas
+    let previous: HashMap<i32, i32> = sqlx::query!(
+        "SELECT DISTINCT ON (appid) appid, playtime_forever
+         FROM playtime_snapshots
+         WHERE steam_id = $1
+         ORDER BY appid, captured_at DESC, id DESC;",
+        steam_id
+    )
+    .fetch_all(&mut *tx)
+    .await?
+    .into_iter()
+    .map(|rec| (rec.appid, rec.playtime_forever))
+    .collect();
+    let first_sync = previous.is_empty();
+
+    let mut appids = Vec::new();
+    let mut playtimes = Vec::new();
+    let mut deltas = Vec::new();
+    for game in &owned_games.games {
+        let playtime = game.playtime_forever as i32;
+        let delta = match previous.get(&(game.appid as i32)) {
+            Some(&last) if last == playtime => continue,
+            // Steam occasionally corrects playtime downwards; that isn't negative play
+            Some(&last) => (playtime - last).max(0),
+            // The first sync is a baseline, but a game added since then was played since then
+            None if first_sync => 0,
+            None => playtime,
+        };
+        appids.push(game.appid as i32);
+        playtimes.push(playtime);
+        deltas.push(delta);
+    }
+
+    sqlx::query!(
+        "INSERT INTO playtime_snapshots (steam_id, appid, playtime_forever, delta_minutes)
+         SELECT $1, * FROM UNNEST($2::INT[], $3::INT[], $4::INT[]);",
+        steam_id,
+        &appids,
+        &playtimes,
+        &deltas,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
     Ok(())
 }
 
+/// Total minutes a user played between `from` (inclusive) and `to` (exclusive), according
+/// to the syncs that happened in that window
+pub async fn get_minutes_played(
+    pool: &PgPool,
+    steam_id: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<i64, sqlx::Error> {
+    let minutes = sqlx::query_scalar!(
+        r#"SELECT COALESCE(SUM(delta_minutes), 0)::BIGINT AS "minutes!"
+           FROM playtime_snapshots
+           WHERE steam_id = $1 AND captured_at >= $2 AND captured_at < $3;"#,
+        steam_id,
+        from,
+        to,
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(minutes)
+}
+
+/// Minutes a user played each game between `from` (inclusive) and `to` (exclusive) as
+/// `(appid, name, minutes)`, most played first. Games without playtime in the window are left out.
+pub async fn get_minutes_played_by_game(
+    pool: &PgPool,
+    steam_id: &str,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<(u32, String, i64)>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"SELECT p.appid, g.name, SUM(p.delta_minutes)::BIGINT AS "minutes!"
+           FROM playtime_snapshots p
+           JOIN games g ON g.steam_id = p.steam_id AND g.appid = p.appid
+           WHERE p.steam_id = $1 AND p.captured_at >= $2 AND p.captured_at < $3
+           GROUP BY p.appid, g.name
+           HAVING SUM(p.delta_minutes) > 0
+           ORDER BY 3 DESC, g.name;"#,
+        steam_id,
+        from,
+        to,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|rec| (rec.appid as u32, rec.name, rec.minutes))
+        .collect())
+}
+
 /// Fetches the user's steam games from the database
 pub async fn get_user_games(pool: &PgPool, steam_id: &str) -> Result<Vec<SteamGame>, sqlx::Error> {
     let records = sqlx::query!(
-        "SELECT name, playtime_forever FROM games WHERE steam_id = $1;",
+        "SELECT appid, name, playtime_forever, img_icon_url, playtime_2weeks, rtime_last_played
+         FROM games WHERE steam_id = $1;",
         steam_id
     )
     .fetch_all(pool)
@@ -77,25 +345,447 @@ pub async fn get_user_games(pool: &PgPool, steam_id: &str) -> Result<Vec<SteamGa
     let games = records
         .into_iter()
         .map(|rec| SteamGame {
+            appid: rec.appid as u32,
             name: rec.name,
             playtime_forever: rec.playtime_forever as u32,
+            img_icon_url: rec.img_icon_url,
+            playtime_2weeks: rec.playtime_2weeks.map(|p| p as u32),
+            rtime_last_played: rec.rtime_last_played.map(|t| t as u32),
         })
         .collect();
 
     Ok(games)
 }
 
-/// Fetches all user steam id's from the database
-pub async fn get_all_steam_ids(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
-    // Query for the steam IDs
-    let steam_ids = sqlx::query!("SELECT steam_id FROM users;")
-        .fetch_all(pool)
-        .await?;
+/// When a user's library was last written by a sync, empty or not, or `None` if it never was
+pub async fn get_games_last_updated(
+    pool: &PgPool,
+    steam_id: &str,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let synced_at = sqlx::query_scalar!(
+        "SELECT MAX(last_synced_at) FROM users WHERE steam_id = $1;",
+        steam_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok(synced_at)
+}
+
+/// Fetches every stored library as `(steam_id, game)` rows, for recommenders that look
+/// across users
+pub async fn get_all_user_games(pool: &PgPool) -> Result<Vec<(String, SteamGame)>, sqlx::Error> {
+    let records = sqlx::query!(
+        "SELECT steam_id, appid, name, playtime_forever, img_icon_url, playtime_2weeks,
+         rtime_last_played FROM games;"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let rows = records
+        .into_iter()
+        .map(|rec| {
+            let game = SteamGame {
+                appid: rec.appid as u32,
+                name: rec.name,
+                playtime_forever: rec.playtime_forever as u32,
+                img_icon_url: rec.img_icon_url,
+                playtime_2weeks: rec.playtime_2weeks.map(|p| p as u32),
+                rtime_last_played: rec.rtime_last_played.map(|t| t as u32),
+            };
+            (rec.steam_id, game)
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+/// Like [`get_all_user_games`], but only for members of one guild when `guild_id` is set
+pub async fn get_server_user_games(
+    pool: &PgPool,
+    guild_id: Option<i64>,
+) -> Result<Vec<(String, SteamGame)>, sqlx::Error> {
+    let Some(guild_id) = guild_id else {
+        return get_all_user_games(pool).await;
+    };
+
+    let records = sqlx::query!(
+        "SELECT g.steam_id, g.appid, g.name, g.playtime_forever, g.img_icon_url,
+         g.playtime_2weeks, g.rtime_last_played
+         FROM games g
+         JOIN users u ON u.steam_id = g.steam_id
+         JOIN guild_members m ON m.discord_id = u.discord_id
+         WHERE m.guild_id = $1;",
+        guild_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let rows = records
+        .into_iter()
+        .map(|rec| {
+            let game = SteamGame {
+                appid: rec.appid as u32,
+                name: rec.name,
+                playtime_forever: rec.playtime_forever as u32,
+                img_icon_url: rec.img_icon_url,
+                playtime_2weeks: rec.playtime_2weeks.map(|p| p as u32),
+                rtime_last_played: rec.rtime_last_played.map(|t| t as u32),
+            };
+            (rec.steam_id, game)
+        })
+        .collect();
+
+    Ok(rows)
+}
+
+/// Fetches the steam ids the daily sync should refresh: members of at least one guild
+/// with sync enabled, plus users who haven't used the bot in any guild yet
+pub async fn get_sync_steam_ids(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    let steam_ids = sqlx::query!(
+        "SELECT u.steam_id FROM users u
+         WHERE NOT EXISTS (SELECT 1 FROM guild_members m WHERE m.discord_id = u.discord_id)
+            OR EXISTS (
+                SELECT 1 FROM guild_members m
+                JOIN guilds g ON g.guild_id = m.guild_id
+                WHERE m.discord_id = u.discord_id AND g.sync_enabled
+            );"
+    )
+    .fetch_all(pool)
+    .await?;
 
     let steam_ids = steam_ids.into_iter().map(|row| row.steam_id).collect();
     Ok(steam_ids)
 }
 
+/// Lock held while a sync run is in progress
+const SYNC_RUN_LOCK: i64 = 1_175_062;
+
+/// Proof that this process is the only one syncing. The lock belongs to its own connection,
+/// which is closed rather than returned to the pool when this is dropped, so the lock is
+/// released even if the run panics.
+pub struct SyncLock {
+    _connection: PoolConnection<Postgres>,
+}
+
+/// Takes the sync lock, or returns `None` when another run holds it, whether it was started
+/// by the schedule, resumed at startup, or runs on another instance
+pub async fn try_lock_sync(pool: &PgPool) -> Result<Option<SyncLock>, sqlx::Error> {
+    let mut connection = pool.acquire().await?;
+    connection.close_on_drop();
+    let locked = sqlx::query_scalar!(
+        r#"SELECT pg_try_advisory_lock($1) AS "locked!";"#,
+        SYNC_RUN_LOCK
+    )
+    .fetch_one(&mut *connection)
+    .await?;
+
+    Ok(locked.then_some(SyncLock {
+        _connection: connection,
+    }))
+}
+
+/// The latest sync run that never finished, usually because the bot restarted mid-sync
+pub async fn get_unfinished_sync_run(pool: &PgPool) -> Result<Option<i64>, sqlx::Error> {
+    sqlx::query_scalar!(
+        "SELECT id FROM sync_runs WHERE finished_at IS NULL ORDER BY id DESC LIMIT 1;"
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Opens a new sync run and returns its id
+pub async fn start_sync_run(pool: &PgPool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar!("INSERT INTO sync_runs DEFAULT VALUES RETURNING id;")
+        .fetch_one(pool)
+        .await
+}
+
+/// The steam ids from [`get_sync_steam_ids`] that run `run_id` hasn't handled yet, skipping
+/// accounts whose last failure is still backing off. Least recently synced come first.
+pub async fn get_pending_sync_steam_ids(
+    pool: &PgPool,
+    run_id: i64,
+) -> Result<Vec<String>, sqlx::Error> {
+    let steam_ids = sqlx::query_scalar!(
+        "SELECT u.steam_id FROM users u
+         LEFT JOIN sync_status s ON s.steam_id = u.steam_id
+         WHERE (
+                NOT EXISTS (SELECT 1 FROM guild_members m WHERE m.discord_id = u.discord_id)
+                OR EXISTS (
+                    SELECT 1 FROM guild_members m
+                    JOIN guilds g ON g.guild_id = m.guild_id
+                    WHERE m.discord_id = u.discord_id AND g.sync_enabled
+                )
+            )
+            AND s.last_run_id IS DISTINCT FROM $1
+            AND (s.next_attempt IS NULL OR s.next_attempt <= NOW())
+         ORDER BY s.last_success NULLS FIRST, u.steam_id;",
+        run_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(steam_ids)
+}
+
+/// Marks an account as synced and clears its retry backoff. `run_id` is `None` for a
+/// `!sync`, which doesn't count towards any scheduled run.
+pub async fn record_sync_success(
+    pool: &PgPool,
+    run_id: Option<i64>,
+    steam_id: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO sync_status (steam_id, last_run_id, last_success)
+         VALUES ($1, $2, NOW())
+         ON CONFLICT (steam_id) DO UPDATE
+         SET last_run_id = COALESCE($2, sync_status.last_run_id), last_success = NOW(),
+             consecutive_failures = 0, next_attempt = NULL;",
+        steam_id,
+        run_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records a failed sync by run `run_id`. The account is skipped for an hour, doubling
+/// with every failure in a row up to a day.
+pub async fn record_sync_failure(
+    pool: &PgPool,
+    run_id: i64,
+    steam_id: &str,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO sync_status (
+             steam_id, last_run_id, last_error, last_error_at, consecutive_failures,
+             next_attempt
+         )
+         VALUES ($1, $2, $3, NOW(), 1, NOW() + INTERVAL '1 hour')
+         ON CONFLICT (steam_id) DO UPDATE
+         SET last_run_id = $2, last_error = $3, last_error_at = NOW(),
+             consecutive_failures = sync_status.consecutive_failures + 1,
+             next_attempt = NOW() + LEAST(
+                 INTERVAL '1 hour' * POWER(2, sync_status.consecutive_failures),
+                 INTERVAL '1 day'
+             );",
+        steam_id,
+        run_id,
+        error
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Closes run `run_id` and returns how many accounts it synced and how many failed
+pub async fn finish_sync_run(pool: &PgPool, run_id: i64) -> Result<(i64, i64), sqlx::Error> {
+    let rec = sqlx::query!(
+        r#"UPDATE sync_runs r
+           SET finished_at = NOW(),
+               users_synced = (
+                   SELECT COUNT(*) FROM sync_status
+                   WHERE last_run_id = r.id AND consecutive_failures = 0
+               ),
+               users_failed = (
+                   SELECT COUNT(*) FROM sync_status
+                   WHERE last_run_id = r.id AND consecutive_failures > 0
+               )
+           WHERE r.id = $1
+           RETURNING users_synced, users_failed;"#,
+        run_id
+    )
+    .fetch_one(pool)
+    .await?;
+
+    Ok((rec.users_synced as i64, rec.users_failed as i64))
+}
+
+/// Starts a user's `!sync` cooldown unless they synced less than `cooldown` ago. Returns
+/// how much of the cooldown is left when it's still running.
+pub async fn claim_manual_sync(
+    pool: &PgPool,
+    discord_id: i64,
+    cooldown: Duration,
+) -> Result<Option<Duration>, sqlx::Error> {
+    let claimed = sqlx::query!(
+        "UPDATE users SET last_manual_sync_at = NOW()
+         WHERE discord_id = $1
+           AND (last_manual_sync_at IS NULL
+                OR last_manual_sync_at <= NOW() - make_interval(secs => $2))
+         RETURNING discord_id;",
+        discord_id,
+        cooldown.as_secs_f64()
+    )
+    .fetch_optional(pool)
+    .await?;
+    if claimed.is_some() {
+        return Ok(None);
+    }
+
+    let remaining = sqlx::query_scalar!(
+        r#"SELECT EXTRACT(EPOCH FROM
+               last_manual_sync_at + make_interval(secs => $2) - NOW()
+           )::FLOAT8 AS "remaining!"
+           FROM users WHERE discord_id = $1;"#,
+        discord_id,
+        cooldown.as_secs_f64()
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(remaining.map(|secs| Duration::from_secs_f64(secs.max(0.0))))
+}
+
+/// Ends a user's `!sync` cooldown early, after a sync that didn't refresh anything
+pub async fn release_manual_sync(pool: &PgPool, discord_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE users SET last_manual_sync_at = NULL WHERE discord_id = $1;",
+        discord_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Lock taken while checking and recording AI usage, so concurrent requests can't all slip
+/// under the same limit
+const LLM_USAGE_LOCK: i64 = 1_175_063;
+
+/// Records an AI request of `tokens` by `discord_id` in `guild_id` (`None` in DMs), unless
+/// that would break one of `limits`, and returns the id of the recorded use. Daily limits
+/// count the last 24 hours, so a refused request says when the oldest counted use stops
+/// counting.
+pub async fn claim_llm_quota(
+    pool: &PgPool,
+    discord_id: i64,
+    guild_id: Option<i64>,
+    limits: &LlmLimits,
+    tokens: i32,
+) -> Result<Result<i64, QuotaExceeded>, sqlx::Error> {
+    let exceeded = |limit, secs: f64| {
+        Ok(Err(QuotaExceeded {
+            limit,
+            retry_after: Duration::from_secs_f64(secs.max(0.0)),
+        }))
+    };
+
+    let mut tx = pool.begin().await?;
+    sqlx::query!("SELECT pg_advisory_xact_lock($1);", LLM_USAGE_LOCK)
+        .execute(&mut *tx)
+        .await?;
+
+    let cooldown_left = sqlx::query_scalar!(
+        r#"SELECT EXTRACT(EPOCH FROM
+               MAX(used_at) + make_interval(secs => $2) - NOW()
+           )::FLOAT8 AS remaining
+           FROM llm_usage WHERE discord_id = $1;"#,
+        discord_id,
+        f64::from(limits.cooldown_secs)
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    if let Some(secs) = cooldown_left.filter(|secs| *secs > 0.0) {
+        return exceeded(QuotaLimit::Cooldown, secs);
+    }
+
+    // The oldest of the last `limit` uses; the member is at the cap while it's in the window
+    let member_full_until = sqlx::query_scalar!(
+        r#"SELECT EXTRACT(EPOCH FROM used_at + INTERVAL '1 day' - NOW())::FLOAT8 AS "remaining!"
+           FROM llm_usage
+           WHERE discord_id = $1 AND used_at > NOW() - INTERVAL '1 day'
+           ORDER BY used_at DESC OFFSET $2 - 1 LIMIT 1;"#,
+        discord_id,
+        limits.member_daily_limit
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(secs) = member_full_until {
+        return exceeded(QuotaLimit::MemberDaily, secs);
+    }
+
+    if let Some(guild_id) = guild_id {
+        let guild_full_until = sqlx::query_scalar!(
+            r#"SELECT EXTRACT(EPOCH FROM used_at + INTERVAL '1 day' - NOW())::FLOAT8 AS "remaining!"
+               FROM llm_usage
+               WHERE guild_id = $1 AND used_at > NOW() - INTERVAL '1 day'
+               ORDER BY used_at DESC OFFSET $2 - 1 LIMIT 1;"#,
+            guild_id,
+            limits.guild_daily_limit
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+        if let Some(secs) = guild_full_until {
+            return exceeded(QuotaLimit::GuildDaily, secs);
+        }
+    }
+
+    // Walking back from the newest use, the first one that takes the total over budget is
+    // the one that has to expire before this request fits
+    let budget_full_until = sqlx::query_scalar!(
+        r#"SELECT EXTRACT(EPOCH FROM used_at + INTERVAL '1 day' - NOW())::FLOAT8 AS "remaining!"
+           FROM (
+               SELECT used_at, SUM(tokens) OVER (ORDER BY used_at DESC, id DESC) AS newer_tokens
+               FROM llm_usage WHERE used_at > NOW() - INTERVAL '1 day'
+           ) usage
+           WHERE newer_tokens + $1 > $2
+           ORDER BY used_at DESC LIMIT 1;"#,
+        i64::from(tokens),
+        limits.daily_token_budget
+    )
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some(secs) = budget_full_until {
+        return exceeded(QuotaLimit::TokenBudget, secs);
+    }
+
+    let usage_id = sqlx::query_scalar!(
+        "INSERT INTO llm_usage (discord_id, guild_id, tokens) VALUES ($1, $2, $3) RETURNING id;",
+        discord_id,
+        guild_id,
+        tokens
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+    sqlx::query!("DELETE FROM llm_usage WHERE used_at < NOW() - INTERVAL '2 days';")
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await?;
+    Ok(Ok(usage_id))
+}
+
+/// Takes back a use recorded by `claim_llm_quota`, for a request the provider never answered
+pub async fn refund_llm_quota(pool: &PgPool, usage_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!("DELETE FROM llm_usage WHERE id = $1;", usage_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Replaces the tokens `claim_llm_quota` reserved for a use with what it actually cost
+pub async fn settle_llm_usage(
+    pool: &PgPool,
+    usage_id: i64,
+    tokens: i32,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE llm_usage SET tokens = $2 WHERE id = $1;",
+        usage_id,
+        tokens
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
 pub async fn check_if_user_exists(pool: &PgPool, steam_id: &str) -> Result<bool, sqlx::Error> {
     // Check database for the user's steam id
     let user = sqlx::query!("SELECT steam_id FROM users WHERE steam_id = $1;", steam_id)
@@ -104,3 +794,474 @@ pub async fn check_if_user_exists(pool: &PgPool, steam_id: &str) -> Result<bool,
 
     Ok(user.is_some())
 }
+
+/// Appids from any library whose store metadata is missing or older than `ttl`, with the
+/// name from the library. Never-fetched apps come first.
+pub async fn get_stale_store_appids(
+    pool: &PgPool,
+    ttl: Duration,
+    limit: i64,
+) -> Result<Vec<(u32, String)>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"SELECT g.appid, MIN(g.name) AS "name!"
+           FROM games g
+           LEFT JOIN store_apps s ON s.appid = g.appid
+           WHERE s.appid IS NULL OR s.fetched_at < NOW() - make_interval(secs => $1)
+           GROUP BY g.appid, s.appid
+           ORDER BY s.appid IS NOT NULL, g.appid
+           LIMIT $2;"#,
+        ttl.as_secs_f64(),
+        limit,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|rec| (rec.appid as u32, rec.name))
+        .collect())
+}
+
+/// Stores an app's store metadata, replacing its previous genres, categories and tags
+pub async fn store_app_details(
+    pool: &PgPool,
+    details: &StoreAppDetails,
+) -> Result<(), sqlx::Error> {
+    let appid = details.appid as i32;
+    let mut tx = pool.begin().await?;
+
+    sqlx::query!(
+        "INSERT INTO store_apps (appid, name, available, is_free, price_cents, currency,
+         metacritic_score, release_date, fetched_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+         ON CONFLICT (appid) DO UPDATE SET name = EXCLUDED.name,
+         available = EXCLUDED.available,
+         is_free = EXCLUDED.is_free,
+         price_cents = EXCLUDED.price_cents,
+         currency = EXCLUDED.currency,
+         metacritic_score = EXCLUDED.metacritic_score,
+         release_date = EXCLUDED.release_date,
+         fetched_at = NOW();",
+        appid,
+        details.name,
+        details.available,
+        details.is_free,
+        details.price_cents,
+        details.currency,
+        details.metacritic_score,
+        details.release_date,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM store_app_genres WHERE appid = $1;", appid)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "INSERT INTO store_app_genres (appid, genre) SELECT $1, UNNEST($2::TEXT[])
+         ON CONFLICT DO NOTHING;",
+        appid,
+        &details.genres,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    sqlx::query!("DELETE FROM store_app_categories WHERE appid = $1;", appid)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "INSERT INTO store_app_categories (appid, category) SELECT $1, UNNEST($2::TEXT[])
+         ON CONFLICT DO NOTHING;",
+        appid,
+        &details.categories,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let (tags, votes): (Vec<String>, Vec<i32>) = details
+        .tags
+        .iter()
+        .map(|(tag, votes)| (tag.clone(), *votes as i32))
+        .unzip();
+    sqlx::query!("DELETE FROM store_app_tags WHERE appid = $1;", appid)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query!(
+        "INSERT INTO store_app_tags (appid, tag, votes)
+         SELECT $1, tag, votes FROM UNNEST($2::TEXT[], $3::INT[]) AS t(tag, votes)
+         ON CONFLICT DO NOTHING;",
+        appid,
+        &tags,
+        &votes,
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Cached store genres for the given appids
+pub async fn get_app_genres(
+    pool: &PgPool,
+    appids: &[u32],
+) -> Result<HashMap<u32, Vec<String>>, sqlx::Error> {
+    let appids: Vec<i32> = appids.iter().map(|&id| id as i32).collect();
+    let records = sqlx::query!(
+        "SELECT appid, genre FROM store_app_genres WHERE appid = ANY($1) ORDER BY appid, genre;",
+        &appids,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut genres: HashMap<u32, Vec<String>> = HashMap::new();
+    for rec in records {
+        genres.entry(rec.appid as u32).or_default().push(rec.genre);
+    }
+    Ok(genres)
+}
+
+/// Cached store categories (`Online Co-op`, `Single-player`, ...) for the given apps.
+/// Apps without cached metadata are missing from the map.
+pub async fn get_app_categories(
+    pool: &PgPool,
+    appids: &[u32],
+) -> Result<HashMap<u32, Vec<String>>, sqlx::Error> {
+    let appids: Vec<i32> = appids.iter().map(|&id| id as i32).collect();
+    let records = sqlx::query!(
+        "SELECT appid, category FROM store_app_categories
+         WHERE appid = ANY($1) ORDER BY appid, category;",
+        &appids,
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut categories: HashMap<u32, Vec<String>> = HashMap::new();
+    for rec in records {
+        categories
+            .entry(rec.appid as u32)
+            .or_default()
+            .push(rec.category);
+    }
+    Ok(categories)
+}
+
+/// Every cached app's store name and tags as `appid -> (name, [(tag, votes)])`
+pub async fn get_all_app_tags(
+    pool: &PgPool,
+) -> Result<HashMap<u32, (String, Vec<(String, u32)>)>, sqlx::Error> {
+    let records = sqlx::query!(
+        "SELECT t.appid, s.name, t.tag, t.votes
+         FROM store_app_tags t
+         JOIN store_apps s ON s.appid = t.appid
+         WHERE s.available;"
+    )
+    .fetch_all(pool)
+    .await?;
+
+    let mut tags: HashMap<u32, (String, Vec<(String, u32)>)> = HashMap::new();
+    for rec in records {
+        tags.entry(rec.appid as u32)
+            .or_insert_with(|| (rec.name, Vec::new()))
+            .1
+            .push((rec.tag, rec.votes as u32));
+    }
+    Ok(tags)
+}
+
+/// Playtime gained per game in the user's most recent sync that recorded any change, as
+/// `(appid, name, minutes)`, most gained first
+pub async fn get_last_sync_gains(
+    pool: &PgPool,
+    steam_id: &str,
+) -> Result<Vec<(u32, String, i64)>, sqlx::Error> {
+    // Every snapshot from one sync shares the transaction's NOW()
+    let records = sqlx::query!(
+        r#"SELECT p.appid, g.name, p.delta_minutes
+           FROM playtime_snapshots p
+           JOIN games g ON g.steam_id = p.steam_id AND g.appid = p.appid
+           WHERE p.steam_id = $1
+             AND p.delta_minutes > 0
+             AND p.captured_at = (
+                 SELECT MAX(captured_at) FROM playtime_snapshots
+                 WHERE steam_id = $1 AND delta_minutes > 0
+             )
+           ORDER BY p.delta_minutes DESC, g.name;"#,
+        steam_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|rec| (rec.appid as u32, rec.name, rec.delta_minutes as i64))
+        .collect())
+}
+
+/// Every available cached app with its store genres and user tags combined, as
+/// `(appid, name, labels)`
+pub async fn get_app_labels(pool: &PgPool) -> Result<Vec<(u32, String, Vec<String>)>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"SELECT s.appid, s.name,
+           ARRAY(SELECT genre FROM store_app_genres g WHERE g.appid = s.appid
+                 UNION
+                 SELECT tag FROM store_app_tags t WHERE t.appid = s.appid) AS "labels!"
+           FROM store_apps s
+           WHERE s.available;"#
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|rec| (rec.appid as u32, rec.name, rec.labels))
+        .collect())
+}
+
+/// Whether a cached app has a store page, with its genres and tags combined, or `None`
+/// when the app isn't cached or its metadata is older than `ttl`
+pub async fn get_fresh_app_labels(
+    pool: &PgPool,
+    appid: u32,
+    ttl: Duration,
+) -> Result<Option<(bool, Vec<String>)>, sqlx::Error> {
+    let record = sqlx::query!(
+        r#"SELECT s.available,
+           ARRAY(SELECT genre FROM store_app_genres g WHERE g.appid = s.appid
+                 UNION
+                 SELECT tag FROM store_app_tags t WHERE t.appid = s.appid) AS "labels!"
+           FROM store_apps s
+           WHERE s.appid = $1 AND s.fetched_at >= NOW() - make_interval(secs => $2);"#,
+        appid as i32,
+        ttl.as_secs_f64(),
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|rec| (rec.available, rec.labels)))
+}
+
+/// Hides a member from (or shows them on) leaderboards. Returns false when the member
+/// hasn't linked a Steam account.
+pub async fn set_leaderboard_opt_out(
+    pool: &PgPool,
+    discord_id: i64,
+    opt_out: bool,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        "UPDATE users SET leaderboard_opt_out = $2 WHERE discord_id = $1;",
+        discord_id,
+        opt_out
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Linked members of a guild ranked by lifetime playtime across their whole library
+pub async fn get_total_playtime_leaderboard(
+    pool: &PgPool,
+    guild_id: i64,
+) -> Result<Vec<LeaderboardEntry>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"SELECT u.discord_id, SUM(g.playtime_forever)::BIGINT AS "minutes!"
+           FROM users u
+           JOIN guild_members m ON m.discord_id = u.discord_id AND m.guild_id = $1
+           JOIN games g ON g.steam_id = u.steam_id
+           WHERE NOT u.leaderboard_opt_out
+           GROUP BY u.discord_id
+           HAVING SUM(g.playtime_forever) > 0
+           ORDER BY 2 DESC, u.discord_id;"#,
+        guild_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|rec| LeaderboardEntry {
+            discord_id: rec.discord_id,
+            minutes: rec.minutes,
+        })
+        .collect())
+}
+
+/// Linked members of a guild ranked by lifetime playtime in one game
+pub async fn get_game_playtime_leaderboard(
+    pool: &PgPool,
+    guild_id: i64,
+    appid: u32,
+) -> Result<Vec<LeaderboardEntry>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"SELECT u.discord_id, g.playtime_forever::BIGINT AS "minutes!"
+           FROM users u
+           JOIN guild_members m ON m.discord_id = u.discord_id AND m.guild_id = $1
+           JOIN games g ON g.steam_id = u.steam_id
+           WHERE NOT u.leaderboard_opt_out AND g.appid = $2 AND g.playtime_forever > 0
+           ORDER BY 2 DESC, u.discord_id;"#,
+        guild_id,
+        appid as i32
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|rec| LeaderboardEntry {
+            discord_id: rec.discord_id,
+            minutes: rec.minutes,
+        })
+        .collect())
+}
+
+/// Linked members of a guild ranked by minutes played between `from` (inclusive) and `to` (exclusive),
+/// from the playtime history
+pub async fn get_playtime_gained_leaderboard(
+    pool: &PgPool,
+    guild_id: i64,
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+) -> Result<Vec<LeaderboardEntry>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"SELECT u.discord_id, SUM(p.delta_minutes)::BIGINT AS "minutes!"
+           FROM users u
+           JOIN guild_members m ON m.discord_id = u.discord_id AND m.guild_id = $1
+           JOIN playtime_snapshots p ON p.steam_id = u.steam_id
+           WHERE NOT u.leaderboard_opt_out AND p.captured_at >= $2 AND p.captured_at < $3
+           GROUP BY u.discord_id
+           HAVING SUM(p.delta_minutes) > 0
+           ORDER BY 2 DESC, u.discord_id;"#,
+        guild_id,
+        from,
+        to
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|rec| LeaderboardEntry {
+            discord_id: rec.discord_id,
+            minutes: rec.minutes,
+        })
+        .collect())
+}
+
+/// Every game owned by a linked member of the guild who shows up on leaderboards, as
+/// `(appid, name, owners)`
+pub async fn get_owned_game_names(
+    pool: &PgPool,
+    guild_id: i64,
+) -> Result<Vec<(u32, String, i64)>, sqlx::Error> {
+    let records = sqlx::query!(
+        r#"SELECT g.appid, MAX(g.name) AS "name!", COUNT(*) AS "owners!"
+           FROM games g
+           JOIN users u ON u.steam_id = g.steam_id
+           JOIN guild_members m ON m.discord_id = u.discord_id AND m.guild_id = $1
+           WHERE NOT u.leaderboard_opt_out
+           GROUP BY g.appid;"#,
+        guild_id
+    )
+    .fetch_all(pool)
+    .await?;
+
+    Ok(records
+        .into_iter()
+        .map(|rec| (rec.appid as u32, rec.name, rec.owners))
+        .collect())
+}
+
+/// Creates a guild's settings row with defaults if it doesn't exist yet
+pub async fn ensure_guild(pool: &PgPool, guild_id: i64) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO guilds (guild_id) VALUES ($1) ON CONFLICT (guild_id) DO NOTHING;",
+        guild_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Gets a guild's settings, or `None` if the guild has no row yet
+pub async fn get_guild_settings(
+    pool: &PgPool,
+    guild_id: i64,
+) -> Result<Option<GuildSettings>, sqlx::Error> {
+    let record = sqlx::query!(
+        "SELECT guild_id, announcement_channel_id, allowed_channel_ids, prefix, llm_persona,
+         sync_enabled, llm_cooldown_secs, llm_member_daily_limit, llm_guild_daily_limit
+         FROM guilds WHERE guild_id = $1;",
+        guild_id
+    )
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(record.map(|rec| GuildSettings {
+        guild_id: rec.guild_id,
+        announcement_channel_id: rec.announcement_channel_id,
+        allowed_channel_ids: rec.allowed_channel_ids,
+        prefix: rec.prefix,
+        llm_persona: rec.llm_persona,
+        sync_enabled: rec.sync_enabled,
+        llm_cooldown_secs: rec.llm_cooldown_secs,
+        llm_member_daily_limit: rec.llm_member_daily_limit,
+        llm_guild_daily_limit: rec.llm_guild_daily_limit,
+    }))
+}
+
+/// Saves every setting of a guild
+pub async fn save_guild_settings(
+    pool: &PgPool,
+    settings: &GuildSettings,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO guilds (guild_id, announcement_channel_id, allowed_channel_ids, prefix,
+         llm_persona, sync_enabled, llm_cooldown_secs, llm_member_daily_limit,
+         llm_guild_daily_limit, updated_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, NOW())
+         ON CONFLICT (guild_id) DO UPDATE SET
+         announcement_channel_id = EXCLUDED.announcement_channel_id,
+         allowed_channel_ids = EXCLUDED.allowed_channel_ids,
+         prefix = EXCLUDED.prefix,
+         llm_persona = EXCLUDED.llm_persona,
+         sync_enabled = EXCLUDED.sync_enabled,
+         llm_cooldown_secs = EXCLUDED.llm_cooldown_secs,
+         llm_member_daily_limit = EXCLUDED.llm_member_daily_limit,
+         llm_guild_daily_limit = EXCLUDED.llm_guild_daily_limit,
+         updated_at = NOW();",
+        settings.guild_id,
+        settings.announcement_channel_id,
+        &settings.allowed_channel_ids,
+        settings.prefix,
+        settings.llm_persona,
+        settings.sync_enabled,
+        settings.llm_cooldown_secs,
+        settings.llm_member_daily_limit,
+        settings.llm_guild_daily_limit,
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records that a Discord user used the bot in a guild
+pub async fn add_guild_member(
+    pool: &PgPool,
+    guild_id: i64,
+    discord_id: i64,
+) -> Result<(), sqlx::Error> {
+    ensure_guild(pool, guild_id).await?;
+    sqlx::query!(
+        "INSERT INTO guild_members (guild_id, discord_id) VALUES ($1, $2)
+         ON CONFLICT DO NOTHING;",
+        guild_id,
+        discord_id
+    )
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,32 @@
+use crate::steam::SteamGame;
+use serde::Serialize;
+
+/// Everything stored about one Discord user, as sent by `!export_my_data`
+#[derive(Debug, Clone, Serialize)]
+pub struct UserData {
+    pub discord_id: i64,
+    pub username: String,
+    pub steam_id: String,
+    pub leaderboard_opt_out: bool,
+    /// Servers the user has run commands in
+    pub guild_ids: Vec<i64>,
+    pub games: Vec<SteamGame>,
+    pub playtime_history: Vec<PlaytimeRecord>,
+}
+
+/// One row of recorded playtime history
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlaytimeRecord {
+    pub appid: u32,
+    pub playtime_forever: u32,
+    pub delta_minutes: u32,
+    /// RFC 3339 timestamp of the sync that recorded it
+    pub captured_at: String,
+}
+
+impl UserData {
+    /// Pretty-printed JSON, ready to attach as a file
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(self)
+    }
+}
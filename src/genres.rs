@@ -0,0 +1,220 @@
+use crate::database::db;
+use crate::store::{StoreAppDetails, StoreClient, STORE_METADATA_TTL};
+use crate::titles::{edit_distance, normalize_title};
+use itertools::Itertools;
+use sqlx::PgPool;
+use std::collections::{HashMap, HashSet};
+use tracing::error;
+
+/// How many close matches are offered when a genre isn't recognised
+const MAX_SUGGESTIONS: usize = 5;
+
+/// Discord shows at most 25 autocomplete choices
+const MAX_COMPLETIONS: usize = 25;
+
+/// Compares genre names ignoring case, spaces and punctuation, so `co-op`, `Co op`
+/// and `COOP` are the same genre
+pub fn genre_key(genre: &str) -> String {
+    genre
+        .chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Restricts recommendations to one store genre or user tag, using the cached store
+/// metadata in `store_apps`. Genres and tags are treated alike because players say
+/// "roguelike" or "co-op" far more often than the store's own "Action" or "RPG".
+pub struct GenreFilter {
+    /// The genre as spelled in the store data, e.g. `Roguelike`
+    pub genre: String,
+    /// Cached apps that have the genre or tag
+    matching: HashSet<u32>,
+    /// Every cached app, with or without the genre
+    cached: HashSet<u32>,
+    /// Normalized store name -> appid, for suggestions that came without an appid
+    titles: HashMap<String, u32>,
+    /// Looks up apps missing from the cache; `None` rejects them instead
+    store: Option<StoreClient>,
+}
+
+impl GenreFilter {
+    /// Builds a filter from `(appid, name, labels)` rows as returned by
+    /// `db::get_app_labels`. An unknown genre returns the closest known ones instead.
+    pub fn from_labels(
+        input: &str,
+        labels: &[(u32, String, Vec<String>)],
+    ) -> Result<Self, Vec<String>> {
+        let key = genre_key(input);
+        let counts = label_counts(labels);
+        let Some((genre, _)) = counts
+            .iter()
+            .filter(|(label, _)| !key.is_empty() && genre_key(label) == key)
+            .max_by_key(|(_, count)| *count)
+        else {
+            return Err(suggest_genres(input, &counts));
+        };
+
+        Ok(GenreFilter {
+            genre: genre.clone(),
+            matching: labels
+                .iter()
+                .filter(|(_, _, app_labels)| app_labels.iter().any(|l| genre_key(l) == key))
+                .map(|(appid, _, _)| *appid)
+                .collect(),
+            cached: labels.iter().map(|(appid, _, _)| *appid).collect(),
+            titles: labels
+                .iter()
+                .map(|(appid, name, _)| (normalize_title(name), *appid))
+                .collect(),
+            store: None,
+        })
+    }
+
+    /// Loads the cached store metadata and builds a filter for `input`. Apps missing from
+    /// the cache are looked up through `store` when they need checking.
+    pub async fn load(
+        pool: &PgPool,
+        input: &str,
+        store: &StoreClient,
+    ) -> Result<Result<Self, Vec<String>>, sqlx::Error> {
+        let labels = db::get_app_labels(pool).await?;
+        Ok(
+            GenreFilter::from_labels(input, &labels).map(|filter| GenreFilter {
+                store: Some(store.clone()),
+                ..filter
+            }),
+        )
+    }
+
+    /// Whether a cached app has the genre; `None` when the app isn't cached
+    pub fn contains(&self, appid: u32) -> Option<bool> {
+        self.cached
+            .contains(&appid)
+            .then(|| self.matching.contains(&appid))
+    }
+
+    /// Whether a suggested game has the genre. Games missing from `store_apps`, or cached
+    /// longer than the metadata TTL, are fetched from the store and cached; anything that
+    /// can't be identified is rejected.
+    pub async fn verify(&self, pool: &PgPool, title: &str, appid: Option<u32>) -> bool {
+        let Some(appid) = appid.or_else(|| self.titles.get(&normalize_title(title)).copied())
+        else {
+            return false;
+        };
+        if let Some(matches) = self.contains(appid) {
+            return matches;
+        }
+        let key = genre_key(&self.genre);
+
+        // Another request may have cached the app since this filter was loaded
+        match db::get_fresh_app_labels(pool, appid, STORE_METADATA_TTL).await {
+            Ok(Some((available, labels))) => {
+                return available && labels.iter().any(|label| genre_key(label) == key);
+            }
+            Ok(None) => {}
+            Err(e) => error!(
+                "Failed to read cached store details for app {}: {:?}",
+                appid, e
+            ),
+        }
+        let Some(store) = &self.store else {
+            return false;
+        };
+
+        let mut details = match store.fetch_app_details(appid).await {
+            Ok(Some(details)) => details,
+            Ok(None) => {
+                // Remember that there's no store page so the app isn't fetched again
+                let details = StoreAppDetails {
+                    appid,
+                    name: title.to_string(),
+                    available: false,
+                    ..Default::default()
+                };
+                if let Err(e) = db::store_app_details(pool, &details).await {
+                    error!("Failed to cache store details for app {}: {:?}", appid, e);
+                }
+                return false;
+            }
+            Err(e) => {
+                error!("Failed to fetch store details for app {}: {:?}", appid, e);
+                return false;
+            }
+        };
+        match store.fetch_app_tags(appid).await {
+            Ok(tags) => details.tags = tags,
+            Err(e) => error!("Failed to fetch tags for app {}: {:?}", appid, e),
+        }
+        if let Err(e) = db::store_app_details(pool, &details).await {
+            error!("Failed to cache store details for app {}: {:?}", appid, e);
+        }
+
+        details
+            .genres
+            .iter()
+            .chain(details.tags.iter().map(|(tag, _)| tag))
+            .any(|label| genre_key(label) == key)
+    }
+}
+
+/// How many cached apps carry each genre or tag, most common first
+pub fn label_counts(labels: &[(u32, String, Vec<String>)]) -> Vec<(String, usize)> {
+    labels
+        .iter()
+        .flat_map(|(_, _, app_labels)| app_labels.iter().unique())
+        .counts()
+        .into_iter()
+        .map(|(label, count)| (label.clone(), count))
+        .sorted_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)))
+        .collect()
+}
+
+/// Known genres that look like a misspelling of `input`, falling back to the most common
+/// genres when nothing is close
+pub fn suggest_genres(input: &str, counts: &[(String, usize)]) -> Vec<String> {
+    let key = genre_key(input);
+    let max_distance = (key.chars().count() / 3).max(2);
+
+    let close: Vec<String> = counts
+        .iter()
+        .filter_map(|(label, count)| {
+            let label_key = genre_key(label);
+            let distance =
+                if !key.is_empty() && (label_key.contains(&key) || key.contains(&label_key)) {
+                    0
+                } else {
+                    edit_distance(&key, &label_key)
+                };
+            (distance <= max_distance).then_some((label, distance, count))
+        })
+        .sorted_by(|a, b| a.1.cmp(&b.1).then_with(|| b.2.cmp(a.2)))
+        .map(|(label, _, _)| label.clone())
+        .take(MAX_SUGGESTIONS)
+        .collect();
+
+    if !close.is_empty() {
+        return close;
+    }
+    counts
+        .iter()
+        .take(MAX_SUGGESTIONS)
+        .map(|(label, _)| label.clone())
+        .collect()
+}
+
+/// Autocomplete choices for a partly typed genre, most common first
+pub fn complete_genres(partial: &str, counts: &[(String, usize)]) -> Vec<String> {
+    let key = genre_key(partial);
+    let (prefix, rest): (Vec<_>, Vec<_>) = counts
+        .iter()
+        .filter(|(label, _)| genre_key(label).contains(&key))
+        .partition(|(label, _)| genre_key(label).starts_with(&key));
+
+    prefix
+        .into_iter()
+        .chain(rest)
+        .take(MAX_COMPLETIONS)
+        .map(|(label, _)| label.clone())
+        .collect()
+}
@@ -0,0 +1,212 @@
+use crate::quota::{
+    DEFAULT_LLM_COOLDOWN_SECS, DEFAULT_LLM_GUILD_DAILY_LIMIT, DEFAULT_LLM_MEMBER_DAILY_LIMIT,
+};
+
+/// Prefix used in DMs and in guilds that haven't picked their own
+pub const DEFAULT_PREFIX: &str = "!";
+
+/// Longest prefix `!settings prefix` accepts
+pub const MAX_PREFIX_CHARS: usize = 5;
+
+/// Longest AI cooldown `!settings ai_cooldown` accepts, in seconds
+pub const MAX_LLM_COOLDOWN_SECS: i32 = 24 * 60 * 60;
+
+/// Per-guild configuration from the `guilds` table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GuildSettings {
+    pub guild_id: i64,
+    /// Where startup announcements go; none are sent when unset
+    pub announcement_channel_id: Option<i64>,
+    /// Channels where commands are answered; empty means every channel
+    pub allowed_channel_ids: Vec<i64>,
+    pub prefix: String,
+    /// Whether prompts without a request get a random persona for variety
+    pub llm_persona: bool,
+    /// Whether the daily sync refreshes this guild's members' libraries
+    pub sync_enabled: bool,
+    /// Overrides of the AI limits; `None` keeps the bot's default
+    pub llm_cooldown_secs: Option<i32>,
+    pub llm_member_daily_limit: Option<i32>,
+    pub llm_guild_daily_limit: Option<i32>,
+}
+
+impl GuildSettings {
+    /// The settings a guild starts with
+    pub fn new(guild_id: i64) -> Self {
+        GuildSettings {
+            guild_id,
+            announcement_channel_id: None,
+            allowed_channel_ids: Vec::new(),
+            prefix: DEFAULT_PREFIX.to_string(),
+            llm_persona: true,
+            sync_enabled: true,
+            llm_cooldown_secs: None,
+            llm_member_daily_limit: None,
+            llm_guild_daily_limit: None,
+        }
+    }
+
+    /// Whether commands may be used in `channel_id`
+    pub fn allows_channel(&self, channel_id: i64) -> bool {
+        self.allowed_channel_ids.is_empty() || self.allowed_channel_ids.contains(&channel_id)
+    }
+
+    pub fn apply(&mut self, change: SettingsChange) {
+        match change {
+            SettingsChange::Show => {}
+            SettingsChange::Announce(channel) => self.announcement_channel_id = channel,
+            SettingsChange::Channels(channels) => self.allowed_channel_ids = channels,
+            SettingsChange::AddChannel(channel) => {
+                if !self.allowed_channel_ids.contains(&channel) {
+                    self.allowed_channel_ids.push(channel);
+                }
+            }
+            SettingsChange::Prefix(prefix) => self.prefix = prefix,
+            SettingsChange::Persona(enabled) => self.llm_persona = enabled,
+            SettingsChange::Sync(enabled) => self.sync_enabled = enabled,
+            SettingsChange::AiCooldown(secs) => self.llm_cooldown_secs = secs,
+            SettingsChange::AiMemberLimit(limit) => self.llm_member_daily_limit = limit,
+            SettingsChange::AiServerLimit(limit) => self.llm_guild_daily_limit = limit,
+        }
+    }
+
+    /// One line per setting, with channels as Discord mentions
+    pub fn describe(&self) -> String {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+        let channels = if self.allowed_channel_ids.is_empty() {
+            "all channels".to_string()
+        } else {
+            self.allowed_channel_ids
+                .iter()
+                .map(|id| format!("<#{}>", id))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        format!(
+            "**Announcements:** {}\n**Command channels:** {}\n**Prefix:** `{}`\n\
+             **AI personas:** {}\n**Daily library sync:** {}\n\
+             **AI limits:** {}s between requests, {} per member and {} per server a day",
+            self.announcement_channel_id
+                .map_or("off".to_string(), |id| format!("<#{}>", id)),
+            channels,
+            self.prefix,
+            on_off(self.llm_persona),
+            on_off(self.sync_enabled),
+            self.llm_cooldown_secs.unwrap_or(DEFAULT_LLM_COOLDOWN_SECS),
+            self.llm_member_daily_limit
+                .unwrap_or(DEFAULT_LLM_MEMBER_DAILY_LIMIT),
+            self.llm_guild_daily_limit
+                .unwrap_or(DEFAULT_LLM_GUILD_DAILY_LIMIT),
+        )
+    }
+}
+
+/// One change requested through `!settings`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SettingsChange {
+    Show,
+    Announce(Option<i64>),
+    Channels(Vec<i64>),
+    AddChannel(i64),
+    Prefix(String),
+    Persona(bool),
+    Sync(bool),
+    /// Seconds between one member's AI requests; `None` restores the default
+    AiCooldown(Option<i32>),
+    /// AI requests per member a day; `None` restores the default
+    AiMemberLimit(Option<i32>),
+    /// AI requests for the whole server a day; `None` restores the default
+    AiServerLimit(Option<i32>),
+}
+
+pub const SETTINGS_HELP: &str = "Usage: `!settings` to show the current settings, or\n\
+     `!settings announce #channel|off`\n\
+     `!settings channels #channel... |all`\n\
+     `!settings prefix <prefix>`\n\
+     `!settings persona on|off`\n\
+     `!settings sync on|off`\n\
+     `!settings ai_cooldown <seconds>|default`\n\
+     `!settings ai_member_limit <requests per day>|default`\n\
+     `!settings ai_server_limit <requests per day>|default`";
+
+impl SettingsChange {
+    /// Parses `!settings <setting> <value...>`
+    pub fn from_args(args: &[&str]) -> Result<Self, String> {
+        let Some((setting, values)) = args.split_first() else {
+            return Ok(SettingsChange::Show);
+        };
+
+        match (setting.to_lowercase().as_str(), values) {
+            ("announce", ["off"]) => Ok(SettingsChange::Announce(None)),
+            ("announce", [channel]) => parse_channel(channel)
+                .map(|id| SettingsChange::Announce(Some(id)))
+                .ok_or_else(|| {
+                    format!("`{}` isn't a channel. Mention one like #general.", channel)
+                }),
+            ("channels", ["all"]) => Ok(SettingsChange::Channels(Vec::new())),
+            ("channels", channels) if !channels.is_empty() => channels
+                .iter()
+                .map(|c| {
+                    parse_channel(c).ok_or_else(|| {
+                        format!("`{}` isn't a channel. Mention one like #general.", c)
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()
+                .map(SettingsChange::Channels),
+            ("prefix", [prefix]) => {
+                if prefix.chars().count() > MAX_PREFIX_CHARS {
+                    Err(format!(
+                        "Prefixes can be at most {} characters.",
+                        MAX_PREFIX_CHARS
+                    ))
+                } else {
+                    Ok(SettingsChange::Prefix(prefix.to_string()))
+                }
+            }
+            ("persona", [value]) => parse_on_off(value).map(SettingsChange::Persona),
+            ("sync", [value]) => parse_on_off(value).map(SettingsChange::Sync),
+            ("ai_cooldown", [value]) => {
+                parse_limit(value, 0, MAX_LLM_COOLDOWN_SECS).map(SettingsChange::AiCooldown)
+            }
+            ("ai_member_limit", [value]) => {
+                parse_limit(value, 1, i32::MAX).map(SettingsChange::AiMemberLimit)
+            }
+            ("ai_server_limit", [value]) => {
+                parse_limit(value, 1, i32::MAX).map(SettingsChange::AiServerLimit)
+            }
+            _ => Err(SETTINGS_HELP.to_string()),
+        }
+    }
+}
+
+/// A channel mention (`<#123>`) or bare channel ID
+fn parse_channel(value: &str) -> Option<i64> {
+    let id = value
+        .strip_prefix("<#")
+        .and_then(|v| v.strip_suffix('>'))
+        .unwrap_or(value);
+    id.parse::<i64>().ok().filter(|id| *id > 0)
+}
+
+fn parse_on_off(value: &str) -> Result<bool, String> {
+    match value.to_lowercase().as_str() {
+        "on" | "true" | "yes" => Ok(true),
+        "off" | "false" | "no" => Ok(false),
+        other => Err(format!("Expected `on` or `off`, not `{}`.", other)),
+    }
+}
+
+/// A number between `min` and `max`, or `default` for `None`
+fn parse_limit(value: &str, min: i32, max: i32) -> Result<Option<i32>, String> {
+    if value.eq_ignore_ascii_case("default") {
+        return Ok(None);
+    }
+    match value.parse::<i32>() {
+        Ok(n) if (min..=max).contains(&n) => Ok(Some(n)),
+        _ => Err(format!(
+            "Expected a number from {} to {}, or `default`, not `{}`.",
+            min, max, value
+        )),
+    }
+}
@@ -0,0 +1,96 @@
+use crate::stats::format_hours;
+use crate::titles::{edit_distance, normalize_title};
+use itertools::Itertools;
+
+/// Members shown per leaderboard page
+pub const PAGE_SIZE: usize = 10;
+
+/// Discord shows at most 25 autocomplete choices
+const MAX_COMPLETIONS: usize = 25;
+
+/// One linked member's place on a leaderboard
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LeaderboardEntry {
+    pub discord_id: i64,
+    pub minutes: i64,
+}
+
+/// How many pages `entries` entries take up; an empty board still has one page
+pub fn page_count(entries: usize) -> usize {
+    entries.div_ceil(PAGE_SIZE).max(1)
+}
+
+/// One page of the board as ranked lines, e.g. `🥇 <@123> — 1,234h`
+pub fn render_page(entries: &[LeaderboardEntry], page: usize) -> String {
+    entries
+        .iter()
+        .enumerate()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .map(|(i, entry)| {
+            let rank = match i {
+                0 => "🥇".to_string(),
+                1 => "🥈".to_string(),
+                2 => "🥉".to_string(),
+                n => format!("**{}.**", n + 1),
+            };
+            format!(
+                "{} <@{}> — {}",
+                rank,
+                entry.discord_id,
+                format_hours(entry.minutes.max(0) as u64)
+            )
+        })
+        .join("\n")
+}
+
+/// Finds the game a member most likely meant by `query` among `(appid, name, owners)`.
+/// Exact normalized titles win, then titles starting with or containing the query, then
+/// close misspellings. Ties go to the game more members own.
+pub fn find_game<'a>(
+    query: &str,
+    games: &'a [(u32, String, i64)],
+) -> Option<&'a (u32, String, i64)> {
+    let query = normalize_title(query);
+    if query.is_empty() {
+        return None;
+    }
+    let max_distance = (query.chars().count() / 4).max(2);
+
+    games
+        .iter()
+        .filter_map(|game| {
+            let title = normalize_title(&game.1);
+            let rank = if title == query {
+                0
+            } else if title.starts_with(&query) {
+                1
+            } else if title.contains(&query) {
+                2
+            } else {
+                match edit_distance(&query, &title) {
+                    d if d <= max_distance => 3 + d,
+                    _ => return None,
+                }
+            };
+            Some((rank, title.len(), game))
+        })
+        .min_by(|a, b| {
+            a.0.cmp(&b.0)
+                .then_with(|| b.2 .2.cmp(&a.2 .2))
+                .then_with(|| a.1.cmp(&b.1))
+        })
+        .map(|(_, _, game)| game)
+}
+
+/// Autocomplete choices for a partly typed game name, most owned first
+pub fn complete_games(partial: &str, games: &[(u32, String, i64)]) -> Vec<String> {
+    let partial = normalize_title(partial);
+    games
+        .iter()
+        .filter(|(_, name, _)| normalize_title(name).contains(&partial))
+        .sorted_by(|a, b| b.2.cmp(&a.2).then_with(|| a.1.cmp(&b.1)))
+        .take(MAX_COMPLETIONS)
+        .map(|(_, name, _)| name.clone())
+        .collect()
+}
@@ -1,4 +1,17 @@
 pub mod bot;
+pub mod cache;
+pub mod candidates;
+pub mod collaborative;
 pub mod database;
+pub mod export;
+pub mod genres;
+pub mod guilds;
+pub mod leaderboard;
 pub mod llm;
+pub mod quota;
+pub mod rate_limit;
+pub mod stats;
 pub mod steam;
+pub mod store;
+pub mod titles;
+pub mod together;
@@ -1,16 +1,34 @@
 mod bot;
+mod cache;
+mod candidates;
+mod collaborative;
 mod database;
+mod export;
+mod genres;
+mod guilds;
+mod leaderboard;
 mod llm;
+mod quota;
+mod rate_limit;
 #[path = "../cron/scheduler.rs"]
 mod scheduler;
+mod stats;
 mod steam;
+mod store;
+mod titles;
+mod together;
 
-use crate::llm::LLMClient;
-use bot::Bot;
-use scheduler::start_scheduler;
-use serenity::model::id::ChannelId;
+use crate::cache::Caches;
+use crate::llm::{LLMClient, LLMConfig};
+use crate::quota::LlmLimits;
+use crate::steam::{SteamClient, DEFAULT_STEAM_API_URL};
+use crate::store::{StoreClient, STEAMSPY_API_URL, STORE_API_URL};
+use bot::{Bot, DEFAULT_LIBRARY_TTL};
+use scheduler::{start_scheduler, DEFAULT_SYNC_SCHEDULE};
 use serenity::prelude::*;
 use std::env;
+use std::sync::Arc;
+use std::time::Duration;
 use dotenvy::dotenv;
 use tracing::error;
 
@@ -23,14 +41,43 @@ async fn main() {
     // Load secrets from .env
     let token = env::var("DISCORD_TOKEN").expect("DISCORD_TOKEN missing");
     let steam_api_key = env::var("STEAM_API_KEY").expect("STEAM_API_KEY missing");
-    let llm_api_key = env::var("LLM_API_KEY").expect("LLM_API_KEY missing");
+    let steam_api_url =
+        env::var("STEAM_API_URL").unwrap_or_else(|_| DEFAULT_STEAM_API_URL.to_string());
+    let llm_config = LLMConfig {
+        provider: env::var("LLM_PROVIDER")
+            .unwrap_or_else(|_| "gemini".to_string())
+            .parse()
+            .expect("Invalid LLM_PROVIDER"),
+        model: env::var("LLM_MODEL").ok(),
+        base_url: env::var("LLM_BASE_URL").ok(),
+        api_key: env::var("LLM_API_KEY").ok(),
+    };
     let db_url = env::var("DATABASE_URL").expect("DATABASE_URL missing");
-    let channel_id = ChannelId::new(
-        env::var("DISCORD_CHANNEL_ID")
-            .expect("DISCORD_CHANNEL_ID missing")
-            .parse::<u64>()
-            .expect("Invalid DISCORD_CHANNEL_ID format"),
-    );
+    let library_ttl = env::var("LIBRARY_TTL_SECS")
+        .map(|secs| Duration::from_secs(secs.parse().expect("Invalid LIBRARY_TTL_SECS")))
+        .unwrap_or(DEFAULT_LIBRARY_TTL);
+    let sync_schedule =
+        env::var("SYNC_SCHEDULE").unwrap_or_else(|_| DEFAULT_SYNC_SCHEDULE.to_string());
+    let mut llm_limits = LlmLimits::default();
+    if let Ok(budget) = env::var("LLM_DAILY_TOKEN_BUDGET") {
+        llm_limits.daily_token_budget = budget.parse().expect("Invalid LLM_DAILY_TOKEN_BUDGET");
+    }
+
+    // Legacy `!` commands stay on during the move to slash commands unless disabled
+    let prefix_commands = env::var("PREFIX_COMMANDS")
+        .map(|v| v != "false" && v != "0")
+        .unwrap_or(true);
+
+    // One client for the bot and the scheduler, so they share a connection pool and rate limit
+    let mut steam = SteamClient::new(&steam_api_url, &steam_api_key);
+    if let Ok(rate) = env::var("STEAM_REQUESTS_PER_SECOND") {
+        let per_second = rate
+            .parse::<f64>()
+            .ok()
+            .filter(|r| *r > 0.0)
+            .expect("Invalid STEAM_REQUESTS_PER_SECOND");
+        steam = steam.with_rate_limit(per_second);
+    }
 
     // Connect to the database
     let connection = sqlx::PgPool::connect(&db_url)
@@ -39,22 +86,42 @@ async fn main() {
 
     // Start the scheduler
     let scheduler_connection = connection.clone();
-    let scheduler_api_key = steam_api_key.clone();
+    let scheduler_steam = steam.clone();
+    let store = StoreClient::new(STORE_API_URL, STEAMSPY_API_URL);
+    let scheduler_store = store.clone();
+    let caches = Arc::new(Caches::default());
+    let scheduler_caches = Arc::clone(&caches);
     let _scheduler = tokio::spawn(async move {
-        if let Err(e) = start_scheduler(scheduler_connection, scheduler_api_key).await {
+        if let Err(e) = start_scheduler(
+            scheduler_connection,
+            scheduler_steam,
+            scheduler_store,
+            &sync_schedule,
+            scheduler_caches,
+        )
+        .await
+        {
             error!("Failed to start scheduler: {:?}", e);
         }
     });
 
     // Start the bot
-    let intents =
-        GatewayIntents::GUILDS | GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+    // Slash commands need no privileged intents; MESSAGE_CONTENT is only for prefix commands
+    let mut intents = GatewayIntents::GUILDS;
+    if prefix_commands {
+        intents |= GatewayIntents::GUILD_MESSAGES | GatewayIntents::MESSAGE_CONTENT;
+    }
 
     let bot = Bot {
         database: connection,
-        steam_api_key,
-        llm_client: LLMClient::new(&llm_api_key),
-        channel_id
+        steam,
+        llm_client: LLMClient::from_config(llm_config).expect("Invalid LLM configuration"),
+        store,
+        guild_settings: Default::default(),
+        prefix_commands,
+        library_ttl,
+        caches,
+        llm_limits,
     };
 
     let mut client = Client::builder(&token, intents)
@@ -0,0 +1,110 @@
+use crate::guilds::GuildSettings;
+use std::time::Duration;
+
+/// Shortest gap between one member's AI requests, unless a server sets its own
+pub const DEFAULT_LLM_COOLDOWN_SECS: i32 = 30;
+/// AI requests per member in any 24 hours, unless a server sets its own
+pub const DEFAULT_LLM_MEMBER_DAILY_LIMIT: i32 = 20;
+/// AI requests per server in any 24 hours, unless the server sets its own
+pub const DEFAULT_LLM_GUILD_DAILY_LIMIT: i32 = 200;
+/// Tokens all servers together may spend in any 24 hours, unless `LLM_DAILY_TOKEN_BUDGET`
+/// says otherwise
+pub const DEFAULT_LLM_DAILY_TOKEN_BUDGET: i64 = 500_000;
+
+/// What one recommendation request reserves of the token budget until the provider reports
+/// what it actually used, and what a prompt counts as when the provider doesn't report it.
+/// A generous estimate of prompt plus response.
+pub const ESTIMATED_TOKENS_PER_REQUEST: i32 = 2_000;
+
+/// The limits that apply to one AI request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LlmLimits {
+    pub cooldown_secs: i32,
+    pub member_daily_limit: i32,
+    pub guild_daily_limit: i32,
+    pub daily_token_budget: i64,
+}
+
+impl Default for LlmLimits {
+    fn default() -> Self {
+        LlmLimits {
+            cooldown_secs: DEFAULT_LLM_COOLDOWN_SECS,
+            member_daily_limit: DEFAULT_LLM_MEMBER_DAILY_LIMIT,
+            guild_daily_limit: DEFAULT_LLM_GUILD_DAILY_LIMIT,
+            daily_token_budget: DEFAULT_LLM_DAILY_TOKEN_BUDGET,
+        }
+    }
+}
+
+impl LlmLimits {
+    /// These limits with a server's overrides applied. The token budget is global and
+    /// can't be changed per server.
+    pub fn for_guild(&self, settings: Option<&GuildSettings>) -> Self {
+        let Some(settings) = settings else {
+            return *self;
+        };
+        LlmLimits {
+            cooldown_secs: settings.llm_cooldown_secs.unwrap_or(self.cooldown_secs),
+            member_daily_limit: settings
+                .llm_member_daily_limit
+                .unwrap_or(self.member_daily_limit),
+            guild_daily_limit: settings
+                .llm_guild_daily_limit
+                .unwrap_or(self.guild_daily_limit),
+            daily_token_budget: self.daily_token_budget,
+        }
+    }
+}
+
+/// Which limit stopped a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaLimit {
+    Cooldown,
+    MemberDaily,
+    GuildDaily,
+    TokenBudget,
+}
+
+/// A request that was refused, and how long until the same request would be allowed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    pub limit: QuotaLimit,
+    pub retry_after: Duration,
+}
+
+impl QuotaExceeded {
+    /// A friendly explanation for the member, pointing to `cf_command` for recommendations
+    /// that don't need the AI
+    pub fn message(&self, cf_command: &str) -> String {
+        let wait = format_wait(self.retry_after);
+        let reason = match self.limit {
+            QuotaLimit::Cooldown => {
+                return format!(
+                    "⏳ Slow down a little! You can ask the AI again in {}.",
+                    wait
+                )
+            }
+            QuotaLimit::MemberDaily => "You've used all of your AI requests for today",
+            QuotaLimit::GuildDaily => "This server has used all of its AI requests for today",
+            QuotaLimit::TokenBudget => "I've used up my AI budget for today",
+        };
+        format!(
+            "⏳ {}. Try again in {}, or use {}, which doesn't need the AI.",
+            reason, wait, cf_command
+        )
+    }
+}
+
+/// `45 seconds`, `1 minute`, `12 minutes`, `3 hours`, always rounded up
+pub fn format_wait(wait: Duration) -> String {
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    let (count, unit) = if secs < 60 {
+        (secs.max(1), "second")
+    } else if secs < 2 * 60 * 60 {
+        (secs.div_ceil(60), "minute")
+    } else {
+        (secs.div_ceil(60 * 60), "hour")
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} {}{}", count, unit, plural)
+}
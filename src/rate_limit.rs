@@ -0,0 +1,85 @@
+use rand::Rng;
+use reqwest::header::RETRY_AFTER;
+use reqwest::Response;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::{sleep, Instant};
+
+/// Backoff before the first retry; doubles on every attempt after that
+const BASE_RETRY_DELAY: Duration = Duration::from_secs(1);
+/// Longest wait between attempts, including waits asked for with `Retry-After`
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(60);
+
+/// A token bucket: up to `capacity` requests at once, refilled at `per_second`.
+/// Share one behind an `Arc` so every caller draws from the same budget.
+pub struct TokenBucket {
+    capacity: f64,
+    per_second: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// Starts full, so the first `capacity` requests go out immediately
+    pub fn new(capacity: u32, per_second: f64) -> Self {
+        assert!(
+            per_second > 0.0,
+            "token bucket refill rate must be positive"
+        );
+        TokenBucket {
+            capacity: capacity.max(1) as f64,
+            per_second,
+            state: Mutex::new(BucketState {
+                tokens: capacity.max(1) as f64,
+                refilled_at: Instant::now(),
+            }),
+        }
+    }
+
+    /// Waits until a token is available and takes it
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+                state.tokens = (state.tokens + elapsed * self.per_second).min(self.capacity);
+                state.refilled_at = now;
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - state.tokens) / self.per_second)
+            };
+            sleep(wait).await;
+        }
+    }
+}
+
+/// The delay a 429 or 503 asks for, in the seconds form Steam uses
+pub fn retry_after(response: &Response) -> Option<Duration> {
+    response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+/// Exponential backoff with equal jitter: half the doubled delay, plus up to the other half
+/// at random, so clients that failed together don't retry together
+pub fn backoff(attempt: u32) -> Duration {
+    let delay = BASE_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt - 1))
+        .min(MAX_RETRY_DELAY);
+    let half = delay / 2;
+    half + half.mul_f64(rand::rng().random::<f64>())
+}
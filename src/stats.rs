@@ -0,0 +1,209 @@
+use crate::steam::SteamGame;
+use itertools::Itertools;
+use std::collections::HashMap;
+
+/// How many genres `!stats` lists
+const TOP_GENRES: usize = 3;
+
+/// Personal library numbers shown by `!stats`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LibraryStats {
+    pub library_size: usize,
+    pub total_minutes: u64,
+    /// Owned games with no recorded playtime at all
+    pub never_played: usize,
+    /// Steam's own two-week playtime, summed over the library
+    pub recent_minutes: u64,
+    /// Store genres by minutes played, most played first
+    pub top_genres: Vec<(String, u64)>,
+    /// The game with the most playtime gained in the latest sync, with the minutes gained
+    pub most_improved: Option<(String, u64)>,
+}
+
+impl LibraryStats {
+    /// `genres` maps appids to store genres; games without cached metadata don't count
+    /// towards any genre. `last_sync_gains` is `(appid, name, minutes)` as returned by
+    /// `db::get_last_sync_gains`.
+    pub fn new(
+        games: &[SteamGame],
+        genres: &HashMap<u32, Vec<String>>,
+        last_sync_gains: &[(u32, String, i64)],
+    ) -> Self {
+        let mut genre_minutes: HashMap<&str, u64> = HashMap::new();
+        for game in games {
+            for genre in genres.get(&game.appid).into_iter().flatten() {
+                *genre_minutes.entry(genre).or_default() += game.playtime_forever as u64;
+            }
+        }
+
+        LibraryStats {
+            library_size: games.len(),
+            total_minutes: games.iter().map(|g| g.playtime_forever as u64).sum(),
+            never_played: games.iter().filter(|g| g.playtime_forever == 0).count(),
+            recent_minutes: games
+                .iter()
+                .filter_map(|g| g.playtime_2weeks)
+                .map(u64::from)
+                .sum(),
+            top_genres: genre_minutes
+                .into_iter()
+                .filter(|(_, minutes)| *minutes > 0)
+                .sorted_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)))
+                .take(TOP_GENRES)
+                .map(|(genre, minutes)| (genre.to_string(), minutes))
+                .collect(),
+            most_improved: last_sync_gains
+                .iter()
+                .filter(|(_, _, minutes)| *minutes > 0)
+                .max_by(|a, b| a.2.cmp(&b.2).then_with(|| b.1.cmp(&a.1)))
+                .map(|(_, name, minutes)| (name.clone(), *minutes as u64)),
+        }
+    }
+
+    /// `(label, value)` pairs in display order, ready to become embed fields
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = vec![
+            (
+                "Total playtime".to_string(),
+                format_hours(self.total_minutes),
+            ),
+            (
+                "Library".to_string(),
+                format_count(self.library_size, "game"),
+            ),
+            (
+                "Pile of shame".to_string(),
+                format!(
+                    "{} never played ({})",
+                    format_count(self.never_played, "game"),
+                    format_percent(self.never_played, self.library_size)
+                ),
+            ),
+            (
+                "Last 2 weeks".to_string(),
+                format_hours(self.recent_minutes),
+            ),
+        ];
+
+        if !self.top_genres.is_empty() {
+            fields.push((
+                "Top genres".to_string(),
+                self.top_genres
+                    .iter()
+                    .map(|(genre, minutes)| format!("{} ({})", genre, format_hours(*minutes)))
+                    .join(", "),
+            ));
+        }
+        if let Some((name, minutes)) = &self.most_improved {
+            fields.push((
+                "Most improved since last sync".to_string(),
+                format!("{} (+{})", name, format_hours(*minutes)),
+            ));
+        }
+        fields
+    }
+}
+
+/// What a `!sync` changed compared to the library stored before it
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SyncChanges {
+    /// Games in the fresh library that weren't stored before
+    pub games_added: usize,
+    /// Playtime gained across the library, including the new games
+    pub minutes_played: u64,
+}
+
+impl SyncChanges {
+    pub fn between(before: &[SteamGame], after: &[SteamGame]) -> Self {
+        let previous: HashMap<u32, u32> = before
+            .iter()
+            .map(|g| (g.appid, g.playtime_forever))
+            .collect();
+
+        let mut changes = SyncChanges::default();
+        for game in after {
+            match previous.get(&game.appid) {
+                Some(minutes) => {
+                    changes.minutes_played += game.playtime_forever.saturating_sub(*minutes) as u64
+                }
+                None => {
+                    changes.games_added += 1;
+                    changes.minutes_played += game.playtime_forever as u64;
+                }
+            }
+        }
+        changes
+    }
+
+    /// One line for the `!sync` reply
+    pub fn summary(&self) -> String {
+        if *self == SyncChanges::default() {
+            return "Nothing changed since your last sync.".to_string();
+        }
+        format!(
+            "{} added, {} played since your last sync.",
+            format_count(self.games_added, "new game"),
+            format_hours(self.minutes_played)
+        )
+    }
+}
+
+/// Minutes as a short duration: `45m` under an hour, `2.5h` under ten hours,
+/// and rounded whole hours with thousands separators (`1,234h`) above that
+pub fn format_hours(minutes: u64) -> String {
+    if minutes < 60 {
+        return format!("{}m", minutes);
+    }
+    let tenths = (minutes as f64 / 6.0).round() as u64;
+    if tenths < 100 {
+        return format!("{}.{}h", tenths / 10, tenths % 10);
+    }
+    format!("{}h", with_separators((minutes + 30) / 60))
+}
+
+/// How long ago something happened, in the largest whole unit: `just now`, `5 minutes ago`,
+/// `3 hours ago`, `2 days ago`
+pub fn format_age(age: chrono::Duration) -> String {
+    let (count, unit) = if age.num_days() > 0 {
+        (age.num_days(), "day")
+    } else if age.num_hours() > 0 {
+        (age.num_hours(), "hour")
+    } else if age.num_minutes() > 0 {
+        (age.num_minutes(), "minute")
+    } else {
+        return "just now".to_string();
+    };
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} {}{} ago", count, unit, plural)
+}
+
+/// `part` as a percentage of `whole` with one decimal place; `0%` for an empty whole
+pub fn format_percent(part: usize, whole: usize) -> String {
+    if whole == 0 {
+        return "0%".to_string();
+    }
+    let percent = (part as f64 * 1000.0 / whole as f64).round() / 10.0;
+    if percent.fract() == 0.0 {
+        format!("{}%", percent as u64)
+    } else {
+        format!("{:.1}%", percent)
+    }
+}
+
+/// `1 game`, `1,204 games`
+pub fn format_count(count: usize, noun: &str) -> String {
+    let plural = if count == 1 { "" } else { "s" };
+    format!("{} {}{}", with_separators(count as u64), noun, plural)
+}
+
+fn with_separators(n: u64) -> String {
+    let digits = n.to_string();
+    let mut out = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, c) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            out.push(',');
+        }
+        out.push(c);
+    }
+    out
+}
@@ -1,18 +1,99 @@
-use reqwest::Client;
+use crate::rate_limit::{backoff, retry_after, TokenBucket, MAX_RETRY_DELAY};
+use reqwest::{Client, Response, StatusCode};
 use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::sync::Arc;
 use tokio::time::{sleep, Duration};
 
-const RETRY_COOLDOWN: u64 = 5;
+/// The public Steam Web API
+pub const DEFAULT_STEAM_API_URL: &str = "https://api.steampowered.com";
 
-#[derive(Deserialize, Debug, Clone, Serialize)]
+/// How long a single request may take before it is retried
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Attempts per call, including the first one
+const MAX_ATTEMPTS: u32 = 5;
+
+/// Steam allows 100,000 calls a day; one a second with short bursts stays well inside that
+const RATE_LIMIT_BURST: u32 = 10;
+const RATE_LIMIT_PER_SECOND: f64 = 1.0;
+
+/// `communityvisibilitystate` of a profile everyone can see
+const VISIBILITY_PUBLIC: u8 = 3;
+
+/// Why a Steam Web API call failed
+#[derive(Debug)]
+pub enum SteamError {
+    /// The profile or its game details aren't public
+    PrivateProfile,
+    /// No profile matches the Steam ID or custom URL
+    NotFound,
+    /// Steam kept answering 429 Too Many Requests
+    RateLimited,
+    /// Steam rejected the API key
+    Unauthorized,
+    /// Network failures, server errors and responses that couldn't be read
+    Upstream(String),
+}
+
+impl fmt::Display for SteamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SteamError::PrivateProfile => write!(f, "Steam profile or game details are private"),
+            SteamError::NotFound => write!(f, "Steam profile not found"),
+            SteamError::RateLimited => write!(f, "rate limited by Steam"),
+            SteamError::Unauthorized => write!(f, "Steam rejected the API key"),
+            SteamError::Upstream(reason) => write!(f, "Steam API error: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for SteamError {}
+
+impl From<reqwest::Error> for SteamError {
+    fn from(e: reqwest::Error) -> Self {
+        SteamError::Upstream(e.to_string())
+    }
+}
+
+impl SteamError {
+    /// The error for a non-success HTTP status
+    fn from_status(status: StatusCode) -> Self {
+        match status {
+            StatusCode::TOO_MANY_REQUESTS => SteamError::RateLimited,
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => SteamError::Unauthorized,
+            StatusCode::NOT_FOUND => SteamError::NotFound,
+            status => SteamError::Upstream(format!("status {}", status.as_u16())),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default, Serialize)]
 pub struct SteamGame {
+    pub appid: u32,
     pub name: String,
     pub playtime_forever: u32,
+    #[serde(default)]
+    pub img_icon_url: Option<String>,
+    /// Only present when the game was played in the last two weeks
+    #[serde(default)]
+    pub playtime_2weeks: Option<u32>,
+    /// Unix timestamp; Steam reports 0 for games that were never launched
+    #[serde(default)]
+    pub rtime_last_played: Option<u32>,
 }
 
 #[derive(Deserialize)]
 struct SteamResponse {
-    response: SteamOwnedGames,
+    response: OwnedGamesData,
+}
+
+/// Steam leaves out `games`, and usually `game_count` too, when game details are private
+#[derive(Deserialize)]
+struct OwnedGamesData {
+    game_count: Option<u32>,
+    games: Option<Vec<SteamGame>>,
 }
 
 #[derive(Deserialize)]
@@ -23,6 +104,15 @@ pub struct SteamOwnedGames {
 #[derive(Deserialize, Debug)]
 pub struct SteamProfile {
     pub personaname: String,
+    /// 1 for private and friends-only profiles, 3 for public ones
+    #[serde(default)]
+    pub communityvisibilitystate: u8,
+}
+
+impl SteamProfile {
+    pub fn is_public(&self) -> bool {
+        self.communityvisibilitystate == VISIBILITY_PUBLIC
+    }
 }
 
 #[derive(Deserialize)]
@@ -35,63 +125,240 @@ struct SteamProfileResponse {
     response: SteamProfileData,
 }
 
-pub async fn fetch_steam_games(
-    api_url: &str,
-    steam_id: &str,
-    api_key: &str,
-) -> anyhow::Result<Vec<SteamGame>> {
-    let mut attempts = 0;
-    let max_retries = 5;
-
-    while attempts < max_retries {
-        let url = format!(
-            "{}/IPlayerService/GetOwnedGames/v1/?key={}&steamid={}&format=json&include_appinfo=true",
-            api_url, api_key, steam_id
-        );
-
-        let client = Client::new();
-        let response = client.get(url).send().await?;
-
-        if response.status().is_success() {
-            let steam_data = response.json::<SteamResponse>().await?.response;
-            return Ok(steam_data.games);
-        } else if response.status().as_u16() == 429 {
-            eprintln!("Steam has limited the rate limit. Retrying in 5 seconds...");
-            sleep(Duration::from_secs(RETRY_COOLDOWN)).await;
-        } else {
+/// A Steam Web API client. Clones share one connection pool and one rate limit, so the bot
+/// and the scheduler together never go over the budget.
+#[derive(Clone)]
+pub struct SteamClient {
+    http: Client,
+    api_url: String,
+    api_key: String,
+    limiter: Arc<TokenBucket>,
+}
+
+impl SteamClient {
+    /// A client for the API at `api_url`, usually [`DEFAULT_STEAM_API_URL`]
+    pub fn new(api_url: &str, api_key: &str) -> Self {
+        let http = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .expect("Failed to build the Steam HTTP client");
+
+        SteamClient {
+            http,
+            api_url: api_url.trim_end_matches('/').to_string(),
+            api_key: api_key.to_string(),
+            limiter: Arc::new(TokenBucket::new(RATE_LIMIT_BURST, RATE_LIMIT_PER_SECOND)),
+        }
+    }
+
+    /// Replaces the shared rate limit with one refilled at `per_second`. Call this before
+    /// cloning the client, or the clones keep the old limit.
+    pub fn with_rate_limit(mut self, per_second: f64) -> Self {
+        self.limiter = Arc::new(TokenBucket::new(RATE_LIMIT_BURST, per_second));
+        self
+    }
+
+    /// Fetches a user's library. A response without any games is checked against the
+    /// profile, so private profiles come back as [`SteamError::PrivateProfile`] rather than
+    /// an empty list.
+    pub async fn fetch_games(&self, steam_id: &str) -> Result<Vec<SteamGame>, SteamError> {
+        let response = self
+            .get(
+                "IPlayerService/GetOwnedGames/v1/",
+                &[
+                    ("steamid", steam_id),
+                    ("format", "json"),
+                    ("include_appinfo", "true"),
+                ],
+            )
+            .await?;
+
+        let steam_data = response.json::<SteamResponse>().await?.response;
+        match (steam_data.games, steam_data.game_count) {
+            (Some(games), _) => Ok(games),
+            (None, Some(0)) => Ok(Vec::new()),
+            // Either the whole profile or just its game details are hidden
+            (None, _) => self
+                .fetch_profile(steam_id)
+                .await
+                .and(Err(SteamError::PrivateProfile)),
+        }
+    }
+
+    /// Fetches a profile's display name and visibility from `GetPlayerSummaries`
+    pub async fn fetch_profile(&self, steam_id: &str) -> Result<SteamProfile, SteamError> {
+        let response = self
+            .get(
+                "ISteamUser/GetPlayerSummaries/v0002/",
+                &[("steamids", steam_id)],
+            )
+            .await?;
+
+        let profile_data = response.json::<SteamProfileResponse>().await?;
+        profile_data
+            .response
+            .players
+            .into_iter()
+            .next()
+            .ok_or(SteamError::NotFound)
+    }
+
+    /// Normalizes anything accepted by [`parse_steam_id_input`] to a SteamID64,
+    /// resolving vanity names through `ISteamUser/ResolveVanityURL`
+    pub async fn resolve_steam_id(&self, input: &str) -> Result<String, SteamError> {
+        let vanity = match parse_steam_id_input(input) {
+            Some(SteamIdInput::SteamId64(id)) => return Ok(id),
+            Some(SteamIdInput::Vanity(vanity)) => vanity,
+            None => return Err(SteamError::NotFound),
+        };
+
+        let response = self
+            .get(
+                "ISteamUser/ResolveVanityURL/v0001/",
+                &[("vanityurl", vanity.as_str())],
+            )
+            .await?;
+
+        let data = response.json::<VanityResponse>().await?.response;
+        match (data.success, data.steamid) {
+            (1, Some(steam_id)) => Ok(steam_id),
+            _ => Err(SteamError::NotFound),
+        }
+    }
+
+    /// Sends a GET to `path` under the API URL, waiting for the rate limiter first.
+    /// Network errors, 429s and server errors are retried with exponential backoff and
+    /// jitter; a `Retry-After` header replaces the computed delay.
+    async fn get(&self, path: &str, query: &[(&str, &str)]) -> Result<Response, SteamError> {
+        let url = format!("{}/{}", self.api_url, path);
+        let mut attempt = 0;
+
+        loop {
+            self.limiter.acquire().await;
+            let result = self
+                .http
+                .get(&url)
+                .query(&[("key", self.api_key.as_str())])
+                .query(query)
+                .send()
+                .await;
+
+            let (error, retry_after) = match result {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let error = SteamError::from_status(response.status());
+                    if matches!(error, SteamError::Unauthorized | SteamError::NotFound) {
+                        // Retrying won't fix a bad key, and unknown ids stay unknown
+                        return Err(error);
+                    }
+                    (error, retry_after(&response))
+                }
+                Err(e) => (SteamError::from(e), None),
+            };
+
+            attempt += 1;
+            if attempt >= MAX_ATTEMPTS {
+                return Err(error);
+            }
+            let delay = retry_after
+                .unwrap_or_else(|| backoff(attempt))
+                .min(MAX_RETRY_DELAY);
             eprintln!(
-                "Failed to fetch Steam games, Status: {}. Retrying...",
-                response.status().as_u16()
+                "Steam request to {} failed ({}). Retrying in {:.1}s...",
+                path,
+                error,
+                delay.as_secs_f64()
             );
-            sleep(Duration::from_secs(2_u64.pow(attempts))).await;
+            sleep(delay).await;
         }
-        attempts += 1;
     }
-    Err(anyhow::anyhow!("Max retries reached"))
 }
 
-pub async fn fetch_steam_profile(steam_id: &str, api_key: &str) -> anyhow::Result<SteamProfile> {
-    let url = format!(
-        "https://api.steampowered.com/ISteamUser/GetPlayerSummaries/v0002/?key={}&steamids={}",
-        api_key, steam_id
-    );
+/// Offset between a 32-bit Steam account id and its SteamID64 for individual accounts
+const STEAM_ID64_BASE: u64 = 76561197960265728;
 
-    let client = Client::new();
-    let response = client.get(&url).send().await?;
-    if response.status().is_success() {
-        let profile_data = response.json::<SteamProfileResponse>().await?;
-        if let Some(profile) = profile_data.response.players.into_iter().next() {
-            Ok(profile)
-        } else {
-            Err(anyhow::anyhow!(
-                "No Steam profile found for the provided Steam ID!"
-            ))
+/// What a user typed into `!link_steam`, before any network lookup
+#[derive(Debug, PartialEq, Eq)]
+pub enum SteamIdInput {
+    /// Already a SteamID64
+    SteamId64(String),
+    /// A custom profile name that has to go through `ResolveVanityURL`
+    Vanity(String),
+}
+
+#[derive(Deserialize)]
+struct VanityData {
+    success: u8,
+    steamid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct VanityResponse {
+    response: VanityData,
+}
+
+/// Parses SteamID64, SteamID2 (`STEAM_0:1:1234`), SteamID3 (`[U:1:2469]`), `/profiles/<id>` and
+/// `/id/<vanity>` profile links, or a bare vanity name
+pub fn parse_steam_id_input(input: &str) -> Option<SteamIdInput> {
+    let input = input.trim().trim_start_matches('<').trim_end_matches('>');
+    if input.is_empty() {
+        return None;
+    }
+
+    // Profile links, with or without scheme / www / trailing slash
+    let without_scheme = input
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.");
+    if let Some(path) = without_scheme.strip_prefix("steamcommunity.com/") {
+        let mut segments = path.split('/').filter(|s| !s.is_empty());
+        return match (segments.next(), segments.next()) {
+            (Some("profiles"), Some(id)) => parse_steam_id64(id).map(SteamIdInput::SteamId64),
+            (Some("id"), Some(vanity)) => Some(SteamIdInput::Vanity(vanity.to_string())),
+            _ => None,
+        };
+    }
+
+    if let Some(id) = parse_steam_id64(input) {
+        return Some(SteamIdInput::SteamId64(id));
+    }
+
+    // SteamID2: STEAM_X:Y:Z -> base + Z * 2 + Y
+    if let Some(rest) = input.to_uppercase().strip_prefix("STEAM_") {
+        let parts: Vec<&str> = rest.split(':').collect();
+        if let [_, y, z] = parts.as_slice() {
+            let y = y.parse::<u64>().ok().filter(|y| *y <= 1)?;
+            let z = z.parse::<u64>().ok()?;
+            return Some(SteamIdInput::SteamId64((STEAM_ID64_BASE + z * 2 + y).to_string()));
         }
-    } else {
-        Err(anyhow::anyhow!(
-            "Failed to fetch Steam profile for steam ID: {}; Status: {}",
-            steam_id,
-            response.status().as_u16()
-        ))
+        return None;
+    }
+
+    // SteamID3: [U:1:W] -> base + W
+    let steam3 = input.trim_start_matches('[').trim_end_matches(']');
+    if let Some(account_id) = steam3
+        .strip_prefix("U:1:")
+        .or_else(|| steam3.strip_prefix("u:1:"))
+    {
+        let account_id = account_id.parse::<u64>().ok()?;
+        return Some(SteamIdInput::SteamId64((STEAM_ID64_BASE + account_id).to_string()));
+    }
+
+    // Anything else that could be a custom URL name
+    if input
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        return Some(SteamIdInput::Vanity(input.to_string()));
     }
+
+    None
+}
+
+fn parse_steam_id64(input: &str) -> Option<String> {
+    let is_id64 = input.len() == 17
+        && input.chars().all(|c| c.is_ascii_digit())
+        && input.parse::<u64>().is_ok_and(|id| id > STEAM_ID64_BASE);
+    is_id64.then(|| input.to_string())
 }
@@ -0,0 +1,334 @@
+use crate::database::db;
+use crate::rate_limit::{backoff, retry_after, TokenBucket, MAX_RETRY_DELAY};
+use reqwest::{Client, Response, StatusCode};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+use tokio::time::{sleep, Duration};
+use tracing::{error, info};
+
+pub const STORE_API_URL: &str = "https://store.steampowered.com";
+pub const STEAMSPY_API_URL: &str = "https://steamspy.com";
+
+/// How long fetched metadata is trusted before the scheduler refreshes it
+pub const STORE_METADATA_TTL: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Most apps refreshed in one scheduler run; the rest are picked up next time
+pub const STORE_REFRESH_BATCH: i64 = 1000;
+
+/// The store API allows roughly 200 requests per 5 minutes
+const STORE_REQUESTS_PER_SECOND: f64 = 0.6;
+/// SteamSpy asks for at most one request a second
+const STEAMSPY_REQUESTS_PER_SECOND: f64 = 1.0;
+const RATE_LIMIT_BURST: u32 = 5;
+
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(15);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Attempts per call, including the first one
+const MAX_ATTEMPTS: u32 = 3;
+
+/// How many of an app's user tags are kept
+const MAX_TAGS: usize = 20;
+
+/// Everything we keep about a game from its Steam store page
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StoreAppDetails {
+    pub appid: u32,
+    pub name: String,
+    /// False when the store has no page for the app (delisted, tools, etc.)
+    pub available: bool,
+    pub is_free: bool,
+    pub price_cents: Option<i32>,
+    pub currency: Option<String>,
+    pub metacritic_score: Option<i32>,
+    pub release_date: Option<String>,
+    pub genres: Vec<String>,
+    pub categories: Vec<String>,
+    /// User-applied tags with their vote counts, most voted first
+    pub tags: Vec<(String, u32)>,
+}
+
+/// Why a store or SteamSpy call failed
+#[derive(Debug)]
+pub enum StoreError {
+    /// Rate limits, server errors and network failures that lasted through every retry
+    Unavailable(String),
+    /// The site answered, but not with anything usable for this app: other 4xx statuses
+    /// and bodies that don't parse
+    Rejected(String),
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Unavailable(reason) => write!(f, "store unavailable: {}", reason),
+            StoreError::Rejected(reason) => write!(f, "store rejected the request: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<reqwest::Error> for StoreError {
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_decode() {
+            StoreError::Rejected(e.to_string())
+        } else {
+            StoreError::Unavailable(e.to_string())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AppDetailsEntry {
+    success: bool,
+    data: Option<AppDetailsData>,
+}
+
+#[derive(Deserialize)]
+struct AppDetailsData {
+    name: String,
+    #[serde(default)]
+    is_free: bool,
+    #[serde(default)]
+    genres: Vec<Description>,
+    #[serde(default)]
+    categories: Vec<Description>,
+    price_overview: Option<PriceOverview>,
+    metacritic: Option<Metacritic>,
+    release_date: Option<ReleaseDate>,
+}
+
+#[derive(Deserialize)]
+struct Description {
+    description: String,
+}
+
+#[derive(Deserialize)]
+struct PriceOverview {
+    currency: String,
+    #[serde(rename = "final")]
+    final_price: i32,
+}
+
+#[derive(Deserialize)]
+struct Metacritic {
+    score: i32,
+}
+
+#[derive(Deserialize)]
+struct ReleaseDate {
+    #[serde(default)]
+    date: String,
+}
+
+#[derive(Deserialize)]
+struct SteamSpyDetails {
+    /// SteamSpy returns `[]` instead of `{}` when an app has no tags
+    #[serde(default)]
+    tags: serde_json::Value,
+}
+
+/// A client for the Steam store and SteamSpy. Clones share one connection pool and each
+/// site's rate limit, so the bot and the scheduler together stay inside them.
+#[derive(Clone)]
+pub struct StoreClient {
+    http: Client,
+    store_url: String,
+    steamspy_url: String,
+    store_limiter: Arc<TokenBucket>,
+    steamspy_limiter: Arc<TokenBucket>,
+}
+
+impl StoreClient {
+    /// A client for the store at `store_url` and SteamSpy at `steamspy_url`, usually
+    /// [`STORE_API_URL`] and [`STEAMSPY_API_URL`]
+    pub fn new(store_url: &str, steamspy_url: &str) -> Self {
+        let http = Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .expect("Failed to build the store HTTP client");
+
+        StoreClient {
+            http,
+            store_url: store_url.trim_end_matches('/').to_string(),
+            steamspy_url: steamspy_url.trim_end_matches('/').to_string(),
+            store_limiter: Arc::new(TokenBucket::new(
+                RATE_LIMIT_BURST,
+                STORE_REQUESTS_PER_SECOND,
+            )),
+            steamspy_limiter: Arc::new(TokenBucket::new(
+                RATE_LIMIT_BURST,
+                STEAMSPY_REQUESTS_PER_SECOND,
+            )),
+        }
+    }
+
+    /// Fetches an app's store page data from `/api/appdetails`.
+    /// Returns `Ok(None)` when Steam reports no store page for the app.
+    pub async fn fetch_app_details(
+        &self,
+        appid: u32,
+    ) -> Result<Option<StoreAppDetails>, StoreError> {
+        let url = format!("{}/api/appdetails", self.store_url);
+        let appid_param = appid.to_string();
+        let query = [
+            ("appids", appid_param.as_str()),
+            ("cc", "us"),
+            ("l", "english"),
+        ];
+        let response = self.get(&self.store_limiter, &url, &query).await?;
+        parse_app_details(appid, response).await
+    }
+
+    /// Fetches an app's user tags from SteamSpy, since the store API doesn't expose them
+    pub async fn fetch_app_tags(&self, appid: u32) -> Result<Vec<(String, u32)>, StoreError> {
+        let url = format!("{}/api.php", self.steamspy_url);
+        let appid_param = appid.to_string();
+        let query = [("request", "appdetails"), ("appid", appid_param.as_str())];
+        let response = self.get(&self.steamspy_limiter, &url, &query).await?;
+        parse_app_tags(response).await
+    }
+
+    /// Sends a GET once `limiter` allows it. Network errors, 429s and server errors are
+    /// retried with the same backoff as Steam Web API calls.
+    async fn get(
+        &self,
+        limiter: &TokenBucket,
+        url: &str,
+        query: &[(&str, &str)],
+    ) -> Result<Response, StoreError> {
+        let mut attempt = 0;
+
+        loop {
+            limiter.acquire().await;
+            let (error, retry_after) = match self.http.get(url).query(query).send().await {
+                Ok(response) if response.status().is_success() => return Ok(response),
+                Ok(response) => {
+                    let status = response.status();
+                    let reason = format!("{} answered with status {}", url, status.as_u16());
+                    if status != StatusCode::TOO_MANY_REQUESTS && !status.is_server_error() {
+                        return Err(StoreError::Rejected(reason));
+                    }
+                    (StoreError::Unavailable(reason), retry_after(&response))
+                }
+                Err(e) => (StoreError::Unavailable(e.to_string()), None),
+            };
+
+            attempt += 1;
+            if attempt >= MAX_ATTEMPTS {
+                return Err(error);
+            }
+            let delay = retry_after
+                .unwrap_or_else(|| backoff(attempt))
+                .min(MAX_RETRY_DELAY);
+            eprintln!(
+                "Store request failed ({}). Retrying in {:.1}s...",
+                error,
+                delay.as_secs_f64()
+            );
+            sleep(delay).await;
+        }
+    }
+}
+
+/// Reads an `/api/appdetails` response for `appid`
+async fn parse_app_details(
+    appid: u32,
+    response: Response,
+) -> Result<Option<StoreAppDetails>, StoreError> {
+    let mut body = response.json::<HashMap<String, AppDetailsEntry>>().await?;
+    let Some(AppDetailsEntry {
+        success: true,
+        data: Some(data),
+    }) = body.remove(&appid.to_string())
+    else {
+        return Ok(None);
+    };
+
+    Ok(Some(StoreAppDetails {
+        appid,
+        name: data.name,
+        available: true,
+        is_free: data.is_free,
+        price_cents: data.price_overview.as_ref().map(|p| p.final_price),
+        currency: data.price_overview.map(|p| p.currency),
+        metacritic_score: data.metacritic.map(|m| m.score),
+        release_date: data.release_date.map(|r| r.date).filter(|d| !d.is_empty()),
+        genres: data.genres.into_iter().map(|g| g.description).collect(),
+        categories: data.categories.into_iter().map(|c| c.description).collect(),
+        tags: Vec::new(),
+    }))
+}
+
+/// Reads a SteamSpy `appdetails` response, keeping the most voted tags
+async fn parse_app_tags(response: Response) -> Result<Vec<(String, u32)>, StoreError> {
+    let details = response.json::<SteamSpyDetails>().await?;
+    let mut tags: Vec<(String, u32)> = details
+        .tags
+        .as_object()
+        .map(|tags| {
+            tags.iter()
+                .filter_map(|(tag, votes)| Some((tag.clone(), votes.as_u64()? as u32)))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    tags.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    tags.truncate(MAX_TAGS);
+    Ok(tags)
+}
+
+/// Fetches store details and tags for every appid in `games` whose cached metadata is
+/// missing or older than `ttl`. Apps the store rejects are remembered as unavailable, so
+/// one bad app can't hold up the rest. Stops early if the store stays rate limited or
+/// down; the next run continues from where this one left off. Returns how many apps were
+/// refreshed.
+pub async fn refresh_store_metadata(
+    pool: &PgPool,
+    store: &StoreClient,
+    ttl: Duration,
+    batch: i64,
+) -> anyhow::Result<usize> {
+    let stale = db::get_stale_store_appids(pool, ttl, batch).await?;
+    info!("Refreshing store metadata for {} apps", stale.len());
+
+    let mut refreshed = 0;
+    for (appid, library_name) in stale {
+        let fetched = store.fetch_app_details(appid).await;
+        if let Err(e @ StoreError::Rejected(_)) = &fetched {
+            error!("Skipping store metadata for app {}: {}", appid, e);
+        }
+        let details = match fetched {
+            Ok(Some(mut details)) => {
+                // Tags are nice to have; a SteamSpy outage shouldn't block genres and prices
+                match store.fetch_app_tags(appid).await {
+                    Ok(tags) => details.tags = tags,
+                    Err(e) => error!("Failed to fetch tags for app {}: {:?}", appid, e),
+                }
+                details
+            }
+            // Remember apps without a usable store page so they aren't retried until the
+            // TTL passes
+            Ok(None) | Err(StoreError::Rejected(_)) => StoreAppDetails {
+                appid,
+                name: library_name,
+                available: false,
+                ..Default::default()
+            },
+            Err(e @ StoreError::Unavailable(_)) => {
+                error!("Stopping store refresh at app {}: {:?}", appid, e);
+                break;
+            }
+        };
+
+        db::store_app_details(pool, &details).await?;
+        refreshed += 1;
+    }
+
+    Ok(refreshed)
+}
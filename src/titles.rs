@@ -0,0 +1,129 @@
+use crate::steam::SteamGame;
+use std::collections::HashSet;
+
+/// Single words that mark a re-release when they end a title
+const EDITION_SUFFIXES: &[&str] = &["goty", "remastered"];
+
+/// Roman numerals as they show up in sequel titles. "I" and "X" are left alone because
+/// they are as often letters as numbers ("Mega Man X").
+const ROMAN_NUMERALS: &[(&str, &str)] = &[
+    ("ii", "2"),
+    ("iii", "3"),
+    ("iv", "4"),
+    ("v", "5"),
+    ("vi", "6"),
+    ("vii", "7"),
+    ("viii", "8"),
+    ("ix", "9"),
+    ("xi", "11"),
+    ("xii", "12"),
+    ("xiii", "13"),
+    ("xiv", "14"),
+    ("xv", "15"),
+    ("xvi", "16"),
+];
+
+/// Normalizes a game title for comparison: lowercase, punctuation and trademark symbols
+/// removed, roman numerals converted, and edition markers at the end ("GOTY",
+/// "Definitive Edition", "Director's Cut") and a leading "The" dropped. Words like
+/// "Ultimate" elsewhere in a title are kept, so "The Ultimate Doom" isn't "DOOM".
+///
+/// `"The Witcher® 3: Wild Hunt - Game of the Year Edition"` becomes `"witcher 3 wild hunt"`.
+pub fn normalize_title(title: &str) -> String {
+    let cleaned: String = title
+        .to_lowercase()
+        .replace('&', " and ")
+        .replace("game of the year", " goty ")
+        .chars()
+        .filter(|c| *c != '\'' && *c != '’')
+        .map(|c| if c.is_alphanumeric() { c } else { ' ' })
+        .collect();
+
+    let mut tokens: Vec<&str> = cleaned
+        .split_whitespace()
+        .map(|t| {
+            ROMAN_NUMERALS
+                .iter()
+                .find(|(roman, _)| *roman == t)
+                .map_or(t, |(_, arabic)| *arabic)
+        })
+        .collect();
+    strip_edition_suffix(&mut tokens);
+
+    let tokens = match tokens.split_first() {
+        Some((&"the", rest)) if !rest.is_empty() => rest,
+        _ => &tokens[..],
+    };
+    tokens.join(" ")
+}
+
+/// Drops edition markers from the end of a title's words, always keeping at least one
+/// word of the title itself
+fn strip_edition_suffix(tokens: &mut Vec<&str>) {
+    loop {
+        let strip = match tokens.as_slice() {
+            [_, _, .., "edition"] | [_, .., "directors", "cut"] => 2,
+            [_, .., last] if EDITION_SUFFIXES.contains(last) => 1,
+            _ => return,
+        };
+        tokens.truncate(tokens.len() - strip);
+    }
+}
+
+/// Levenshtein distance between two strings, counted in characters
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The title before a subtitle (`"Hades II: Deluxe"` -> `"hades 2"`), if there is one
+fn normalize_base_title(title: &str) -> Option<String> {
+    let (base, _) = title.split_once(':').or_else(|| title.split_once(" - "))?;
+    let base = normalize_title(base);
+    (!base.is_empty()).then_some(base)
+}
+
+/// A user's library indexed for "do they already own this?" checks
+pub struct OwnedLibrary {
+    appids: HashSet<u32>,
+    titles: HashSet<String>,
+    base_titles: HashSet<String>,
+}
+
+impl OwnedLibrary {
+    pub fn new(games: &[SteamGame]) -> Self {
+        OwnedLibrary {
+            appids: games.iter().map(|g| g.appid).collect(),
+            titles: games.iter().map(|g| normalize_title(&g.name)).collect(),
+            base_titles: games
+                .iter()
+                .filter_map(|g| normalize_base_title(&g.name))
+                .collect(),
+        }
+    }
+
+    /// Whether a suggested title (and appid, when known) is already in the library.
+    /// A subtitle on only one side still matches ("The Witcher 3" vs
+    /// "The Witcher 3: Wild Hunt"), but two different subtitles of a franchise do not.
+    pub fn owns(&self, title: &str, appid: Option<u32>) -> bool {
+        if appid.is_some_and(|id| self.appids.contains(&id)) {
+            return true;
+        }
+
+        let normalized = normalize_title(title);
+        if self.titles.contains(&normalized) || self.base_titles.contains(&normalized) {
+            return true;
+        }
+
+        normalize_base_title(title).is_some_and(|base| self.titles.contains(&base))
+    }
+}
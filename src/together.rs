@@ -0,0 +1,83 @@
+use crate::steam::SteamGame;
+use std::collections::{HashMap, HashSet};
+
+/// How many shared games `!together` lists
+pub const SHARED_GAMES_SHOWN: usize = 10;
+
+/// Store categories that mean a game can be played as a group
+const MULTIPLAYER_MARKERS: &[&str] = &["multi-player", "co-op", "pvp", "mmo", "split screen"];
+
+/// A game every member of the group owns
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedGame {
+    pub appid: u32,
+    pub name: String,
+    /// Lifetime minutes per member, in the order the libraries were given
+    pub minutes: Vec<u32>,
+    /// `Some(true)` when the store lists a multiplayer category, `None` when the game has no
+    /// cached store metadata
+    pub multiplayer: Option<bool>,
+    pub score: f64,
+}
+
+/// Whether a game's store categories include any kind of multiplayer
+pub fn is_multiplayer(categories: &[String]) -> bool {
+    categories.iter().any(|category| {
+        let category = category.to_lowercase();
+        MULTIPLAYER_MARKERS.iter().any(|m| category.contains(m))
+    })
+}
+
+/// Games owned by every library, best for the group first.
+///
+/// Each member's playtime counts as `ln(1 + hours)`, so one player's 2,000 hours can't carry
+/// a game on their own. The combined score is the sum of those, scaled by how evenly the
+/// group plays it: a game everyone has sunk time into beats one only a single member knows.
+pub fn rank_shared_games(
+    libraries: &[Vec<SteamGame>],
+    categories: &HashMap<u32, Vec<String>>,
+) -> Vec<SharedGame> {
+    let Some((first, rest)) = libraries.split_first() else {
+        return Vec::new();
+    };
+    let indexed: Vec<HashMap<u32, &SteamGame>> = rest
+        .iter()
+        .map(|library| library.iter().map(|g| (g.appid, g)).collect())
+        .collect();
+
+    let mut seen = HashSet::new();
+    let mut shared: Vec<SharedGame> = first
+        .iter()
+        .filter(|game| seen.insert(game.appid))
+        .filter_map(|game| {
+            let mut minutes = vec![game.playtime_forever];
+            for library in &indexed {
+                minutes.push(library.get(&game.appid)?.playtime_forever);
+            }
+
+            let weights: Vec<f64> = minutes
+                .iter()
+                .map(|m| (1.0 + *m as f64 / 60.0).ln())
+                .collect();
+            let combined: f64 = weights.iter().sum();
+            let max = weights.iter().cloned().fold(0.0, f64::max);
+            let min = weights.iter().cloned().fold(f64::INFINITY, f64::min);
+            let balance = if max > 0.0 { min / max } else { 0.0 };
+
+            Some(SharedGame {
+                appid: game.appid,
+                name: game.name.clone(),
+                multiplayer: categories.get(&game.appid).map(|c| is_multiplayer(c)),
+                score: combined * (0.5 + 0.5 * balance),
+                minutes,
+            })
+        })
+        .collect();
+
+    shared.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    shared
+}
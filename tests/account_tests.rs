@@ -0,0 +1,194 @@
+mod common;
+
+use common::{database_url, generate_test_discord_id, generate_test_steam_id};
+use game_recommender::database::db;
+use game_recommender::steam::{SteamGame, SteamOwnedGames};
+
+fn library(playtime_forever: u32) -> SteamOwnedGames {
+    SteamOwnedGames {
+        games: vec![
+            SteamGame {
+                appid: 570,
+                name: "Dota 2".to_string(),
+                playtime_forever,
+                ..Default::default()
+            },
+            SteamGame {
+                appid: 1145360,
+                name: "Hades".to_string(),
+                playtime_forever: 30,
+                ..Default::default()
+            },
+        ],
+    }
+}
+
+/// Links a new user in a guild with two synced games
+async fn linked_user(connection: &sqlx::PgPool) -> (i64, String) {
+    let discord_id = generate_test_discord_id();
+    let steam_id = generate_test_steam_id();
+    db::link_steam(connection, "member", discord_id, &steam_id)
+        .await
+        .expect("Failed to link user");
+    db::add_guild_member(connection, generate_test_discord_id(), discord_id)
+        .await
+        .expect("Failed to add guild member");
+    for playtime in [60, 90] {
+        db::store_steam_games(connection, &steam_id, library(playtime))
+            .await
+            .expect("Failed to store games");
+    }
+    (discord_id, steam_id)
+}
+
+#[tokio::test]
+async fn test_unlink_deletes_everything() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let (discord_id, steam_id) = linked_user(&connection).await;
+
+    let unlinked = db::unlink_steam(&connection, discord_id)
+        .await
+        .expect("Failed to unlink");
+    assert_eq!(unlinked, Some(steam_id.clone()));
+
+    assert_eq!(
+        db::get_steam_id(&connection, discord_id).await.unwrap(),
+        None
+    );
+    assert!(db::get_user_games(&connection, &steam_id)
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(db::get_user_data(&connection, discord_id)
+        .await
+        .unwrap()
+        .is_none());
+    let snapshots: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM playtime_snapshots WHERE steam_id = $1")
+            .bind(&steam_id)
+            .fetch_one(&connection)
+            .await
+            .unwrap();
+    assert_eq!(snapshots, 0);
+    let memberships: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM guild_members WHERE discord_id = $1")
+            .bind(discord_id)
+            .fetch_one(&connection)
+            .await
+            .unwrap();
+    assert_eq!(memberships, 0);
+
+    // Nothing left to unlink the second time
+    assert_eq!(
+        db::unlink_steam(&connection, discord_id).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_relink_drops_old_account_data() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let (discord_id, old_steam_id) = linked_user(&connection).await;
+    let new_steam_id = generate_test_steam_id();
+    assert!(db::get_games_last_updated(&connection, &old_steam_id)
+        .await
+        .unwrap()
+        .is_some());
+
+    let previous = db::relink_steam(&connection, discord_id, &new_steam_id)
+        .await
+        .expect("Failed to relink");
+    assert_eq!(previous, Some(old_steam_id.clone()));
+    assert_eq!(
+        db::get_steam_id(&connection, discord_id).await.unwrap(),
+        Some(new_steam_id.clone())
+    );
+    assert!(db::get_user_games(&connection, &old_steam_id)
+        .await
+        .unwrap()
+        .is_empty());
+    // The old account's sync time doesn't make the new one look fresh
+    assert_eq!(
+        db::get_games_last_updated(&connection, &new_steam_id)
+            .await
+            .unwrap(),
+        None
+    );
+
+    // Unlinked users have nothing to switch
+    assert_eq!(
+        db::relink_steam(&connection, generate_test_discord_id(), "anything")
+            .await
+            .unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+async fn test_export_user_data() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let (discord_id, steam_id) = linked_user(&connection).await;
+
+    let data = db::get_user_data(&connection, discord_id)
+        .await
+        .expect("Failed to export")
+        .expect("User missing");
+    assert_eq!(data.steam_id, steam_id);
+    assert_eq!(data.guild_ids.len(), 1);
+    let appids: Vec<u32> = data.games.iter().map(|g| g.appid).collect();
+    assert_eq!(appids, vec![570, 1145360]);
+    // The first sync is a baseline, the second records 30 more minutes of Dota 2
+    let deltas: Vec<(u32, u32)> = data
+        .playtime_history
+        .iter()
+        .map(|r| (r.appid, r.delta_minutes))
+        .collect();
+    assert_eq!(deltas, vec![(570, 0), (1145360, 0), (570, 30)]);
+
+    let json: serde_json::Value =
+        serde_json::from_str(&data.to_json().expect("Failed to serialize")).unwrap();
+    assert_eq!(json["discord_id"], discord_id);
+    assert_eq!(json["games"][0]["name"], "Dota 2");
+    assert_eq!(json["playtime_history"][2]["playtime_forever"], 90);
+}
+
+#[tokio::test]
+async fn test_games_require_a_linked_user() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let steam_id = generate_test_steam_id();
+
+    let error = db::store_steam_games(&connection, &steam_id, library(60))
+        .await
+        .expect_err("Games were stored without a user");
+    let error = error.into_database_error().expect("Not a database error");
+    assert!(error.is_foreign_key_violation());
+    assert!(db::get_user_games(&connection, &steam_id)
+        .await
+        .unwrap()
+        .is_empty());
+}
+
+#[tokio::test]
+async fn test_deleting_user_cascades_to_games() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let (_, steam_id) = linked_user(&connection).await;
+
+    sqlx::query("DELETE FROM users WHERE steam_id = $1")
+        .bind(&steam_id)
+        .execute(&connection)
+        .await
+        .expect("Failed to delete user");
+
+    assert!(db::get_user_games(&connection, &steam_id)
+        .await
+        .unwrap()
+        .is_empty());
+    let orphans: i64 = sqlx::query_scalar(
+        "SELECT COUNT(*) FROM games g
+         WHERE NOT EXISTS (SELECT 1 FROM users u WHERE u.steam_id = g.steam_id)",
+    )
+    .fetch_one(&connection)
+    .await
+    .unwrap();
+    assert_eq!(orphans, 0);
+}
@@ -0,0 +1,76 @@
+use game_recommender::bot::{truncate_chars, Engine, LeaderboardCommand, RecommendOptions};
+
+#[test]
+fn test_recommend_options_from_args() {
+    assert_eq!(
+        RecommendOptions::from_args(&["chill", "roguelike"]),
+        Ok(RecommendOptions {
+            engine: Engine::Hybrid,
+            genre: None,
+            query: "chill roguelike".to_string(),
+        })
+    );
+    assert_eq!(
+        RecommendOptions::from_args(&["--engine", "cf"]),
+        Ok(RecommendOptions {
+            engine: Engine::Cf,
+            genre: None,
+            query: String::new(),
+        })
+    );
+    assert_eq!(
+        RecommendOptions::from_args(&["--genre", "free-to-play", "short", "--engine", "llm"]),
+        Ok(RecommendOptions {
+            engine: Engine::Llm,
+            genre: Some("free-to-play".to_string()),
+            query: "short".to_string(),
+        })
+    );
+    assert!(RecommendOptions::from_args(&["--genre"]).is_err());
+    assert_eq!(
+        RecommendOptions::from_args(&["--engine"]),
+        Err("`--engine` needs a value: `hybrid`, `llm` or `cf`.".to_string())
+    );
+    assert_eq!(
+        RecommendOptions::from_args(&["--engine", "magic"]),
+        Err("Unknown engine `magic`. Use `hybrid`, `llm` or `cf`.".to_string())
+    );
+    assert!(RecommendOptions::from_args(&["--mood", "sad"]).is_err());
+}
+
+#[test]
+fn test_leaderboard_command_from_args() {
+    assert_eq!(
+        LeaderboardCommand::from_args(&[]),
+        Ok(LeaderboardCommand::Total)
+    );
+    assert_eq!(
+        LeaderboardCommand::from_args(&["WEEK"]),
+        Ok(LeaderboardCommand::Week)
+    );
+    assert_eq!(
+        LeaderboardCommand::from_args(&["hide"]),
+        Ok(LeaderboardCommand::Hide)
+    );
+    assert_eq!(
+        LeaderboardCommand::from_args(&["game", "slay", "the", "spire"]),
+        Ok(LeaderboardCommand::Game("slay the spire".to_string()))
+    );
+    assert_eq!(
+        LeaderboardCommand::from_args(&["hades"]),
+        Ok(LeaderboardCommand::Game("hades".to_string()))
+    );
+    assert!(LeaderboardCommand::from_args(&["game"]).is_err());
+}
+
+#[test]
+fn test_truncate_chars() {
+    assert_eq!(truncate_chars("Hades", 5), "Hades");
+    assert_eq!(truncate_chars("Dead Cells", 5), "Dead…");
+    // Cuts on character boundaries, not bytes
+    assert_eq!(truncate_chars("ゼルダの伝説", 4), "ゼルダ…");
+    assert_eq!(
+        truncate_chars(&"a".repeat(2000), 1024).chars().count(),
+        1024
+    );
+}
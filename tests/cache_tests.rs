@@ -0,0 +1,137 @@
+use game_recommender::cache::*;
+use game_recommender::llm::Recommendation;
+use game_recommender::steam::SteamGame;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn recommendation_key(steam_id: &str, intent: Option<&str>) -> RecommendationKey {
+    RecommendationKey {
+        steam_id: steam_id.to_string(),
+        engine: "llm",
+        intent: intent.map(str::to_string),
+        genre: None,
+        guild_id: Some(1),
+        persona: true,
+    }
+}
+
+#[tokio::test]
+async fn test_cache_counts_hits_and_misses() {
+    let cache: Cache<String, u32> = Cache::new(10, Duration::from_secs(60));
+
+    assert_eq!(cache.get(&"a".to_string()).await, None);
+    let loaded = cache
+        .get_or_try_insert("a".to_string(), async { Ok::<_, String>(1) })
+        .await;
+    assert_eq!(loaded, Ok(1));
+
+    // Loaded values are served from memory after that
+    let loaded = cache
+        .get_or_try_insert("a".to_string(), async {
+            Err("should not load".to_string())
+        })
+        .await;
+    assert_eq!(loaded, Ok(1));
+
+    let stats = cache.stats();
+    assert_eq!((stats.hits, stats.misses), (1, 2));
+    assert_eq!(
+        format!(
+            "{}",
+            CacheStats {
+                entries: 1,
+                ..stats
+            }
+        ),
+        "1 hits, 2 misses (33.3% hit rate), 1 entries"
+    );
+}
+
+#[tokio::test]
+async fn test_cache_skips_failed_loads_and_expires() {
+    let cache: Cache<String, u32> = Cache::new(10, Duration::from_millis(200));
+
+    let failed = cache
+        .get_or_try_insert("a".to_string(), async { Err("database down") })
+        .await;
+    assert_eq!(failed, Err(Arc::new("database down")));
+    assert_eq!(cache.get(&"a".to_string()).await, None);
+
+    let loaded = cache
+        .get_or_try_insert("a".to_string(), async { Ok::<_, ()>(1) })
+        .await;
+    assert_eq!(loaded, Ok(1));
+    assert_eq!(cache.get(&"a".to_string()).await, Some(1));
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    assert_eq!(cache.get(&"a".to_string()).await, None);
+}
+
+#[tokio::test]
+async fn test_concurrent_loads_of_one_key_share_a_single_load() {
+    let cache: Cache<String, u32> = Cache::new(10, Duration::from_secs(60));
+    let loads = AtomicUsize::new(0);
+    let load = || async {
+        loads.fetch_add(1, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        Ok::<_, ()>(7)
+    };
+
+    let (first, second) = tokio::join!(
+        cache.get_or_try_insert("a".to_string(), load()),
+        cache.get_or_try_insert("a".to_string(), load()),
+    );
+
+    assert_eq!((first, second), (Ok(7), Ok(7)));
+    assert_eq!(loads.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn test_invalidate_user_clears_library_and_recommendations() {
+    let caches = Caches::default();
+    let library = StoredLibrary {
+        games: vec![SteamGame {
+            appid: 10,
+            name: "Test Game 1".to_string(),
+            ..Default::default()
+        }],
+        synced_at: None,
+    };
+    let recommendations = vec![Recommendation {
+        title: "Hades".to_string(),
+        reason: "Fast runs".to_string(),
+        steam_appid: Some(1145360),
+    }];
+
+    for steam_id in ["synced", "untouched"] {
+        caches
+            .libraries
+            .get_or_try_insert(steam_id.to_string(), async { Ok::<_, ()>(library.clone()) })
+            .await
+            .unwrap();
+        for intent in [None, Some("chill")] {
+            caches
+                .recommendations
+                .get_or_try_insert(recommendation_key(steam_id, intent), async {
+                    Ok::<_, ()>(recommendations.clone())
+                })
+                .await
+                .unwrap();
+        }
+    }
+
+    caches.invalidate_user("synced").await;
+
+    assert!(caches.libraries.get(&"synced".to_string()).await.is_none());
+    for intent in [None, Some("chill")] {
+        let key = recommendation_key("synced", intent);
+        assert!(caches.recommendations.get(&key).await.is_none());
+    }
+    assert!(caches
+        .libraries
+        .get(&"untouched".to_string())
+        .await
+        .is_some());
+    let key = recommendation_key("untouched", Some("chill"));
+    assert!(caches.recommendations.get(&key).await.is_some());
+}
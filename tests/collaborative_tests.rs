@@ -0,0 +1,96 @@
+mod common;
+
+use common::{database_url, generate_test_steam_id, link_test_user};
+use game_recommender::collaborative::*;
+use game_recommender::database::db;
+use game_recommender::steam::{SteamGame, SteamOwnedGames};
+
+fn game(appid: u32, name: &str, hours: u32) -> SteamGame {
+    SteamGame {
+        appid,
+        name: name.to_string(),
+        playtime_forever: hours * 60,
+        ..Default::default()
+    }
+}
+
+fn library(steam_id: &str, games: Vec<SteamGame>) -> Vec<(String, SteamGame)> {
+    games
+        .into_iter()
+        .map(|g| (steam_id.to_string(), g))
+        .collect()
+}
+
+#[test]
+fn test_cf_model_recommends_unowned_games_of_similar_players() {
+    let mut rows = library("me", vec![game(1, "Hades", 100), game(2, "Dead Cells", 50)]);
+    // Shares my taste and plays Slay the Spire a lot
+    rows.extend(library(
+        "roguelike_fan",
+        vec![game(1, "Hades", 80), game(3, "Slay the Spire", 300)],
+    ));
+    // Also shares my taste
+    rows.extend(library(
+        "other_fan",
+        vec![game(2, "Dead Cells", 40), game(3, "Slay the Spire", 20), game(4, "Balatro", 10)],
+    ));
+    // Nothing in common with me
+    rows.extend(library("sports_fan", vec![game(5, "FIFA", 500)]));
+    // Owned but never played still counts as owned
+    rows.push(("me".to_string(), game(4, "Balatro", 0)));
+
+    let model = CfModel::build(&rows);
+    let recommendations = model.recommend("me", 5);
+
+    let names: Vec<&str> = recommendations.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["Slay the Spire"]);
+    assert_eq!(recommendations[0].appid, 3);
+    assert_eq!(recommendations[0].players, 2);
+
+    assert!(model.recommend("unknown_user", 5).is_empty());
+
+    // Server-wide fallback for users with nothing in common with anyone
+    let popular = model.popular("sports_fan", 2);
+    let names: Vec<&str> = popular.iter().map(|r| r.name.as_str()).collect();
+    assert_eq!(names, vec!["Hades", "Slay the Spire"]);
+}
+
+#[tokio::test]
+async fn test_recommend_for_user_from_database() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let me = generate_test_steam_id();
+    let friend = generate_test_steam_id();
+
+    // Appids unique to this run so other tests' rows can't influence the result
+    let base = 10_000_000 + rand::random::<u32>() % 1_000_000_000;
+    let shared = game(base, "Shared Favourite", 50);
+    let suggestion = game(base + 1, "Friend's Favourite", 200);
+    link_test_user(&connection, &me).await;
+    link_test_user(&connection, &friend).await;
+
+    db::store_steam_games(
+        &connection,
+        &me,
+        SteamOwnedGames {
+            games: vec![shared.clone()],
+        },
+    )
+    .await
+    .expect("Failed to store steam games");
+    db::store_steam_games(
+        &connection,
+        &friend,
+        SteamOwnedGames {
+            games: vec![shared, suggestion],
+        },
+    )
+    .await
+    .expect("Failed to store steam games");
+
+    let recommendations = recommend_for_user(&connection, &me, None, 5)
+        .await
+        .expect("Failed to build recommendations");
+    assert_eq!(recommendations.len(), 1);
+    assert_eq!(recommendations[0].appid, base + 1);
+    assert_eq!(recommendations[0].name, "Friend's Favourite");
+}
@@ -0,0 +1,40 @@
+//! Fixtures shared by the integration tests. Each test crate uses only some of them.
+#![allow(dead_code)]
+
+use dotenvy::dotenv;
+use game_recommender::database::db;
+use std::env;
+use uuid::Uuid;
+
+/// Fetch the DATABASE_TEST_URL once
+pub fn database_url() -> String {
+    dotenv().ok();
+    env::var("DATABASE_TEST_URL").expect("DATABASE_TEST_URL not set")
+}
+
+/// Helper to generate random steam id for testing
+pub fn generate_test_steam_id() -> String {
+    format!("test_steam_{}", Uuid::new_v4())
+}
+
+/// Helper to generate random discord id for testing
+pub fn generate_test_discord_id() -> i64 {
+    rand::random::<i64>().abs()
+}
+
+/// Appid that no other test uses
+pub fn generate_test_appid() -> u32 {
+    2_000_000_000 + rand::random::<u32>() % 100_000_000
+}
+
+/// Links a throwaway Discord user, since games can only be stored for linked accounts
+pub async fn link_test_user(connection: &sqlx::PgPool, steam_id: &str) {
+    db::link_steam(
+        connection,
+        "test_user",
+        rand::random::<i64>().abs(),
+        steam_id,
+    )
+    .await
+    .expect("Failed to link test user");
+}
@@ -0,0 +1,163 @@
+mod common;
+
+use common::{database_url, generate_test_appid};
+use game_recommender::database::db;
+use game_recommender::genres::*;
+use game_recommender::store::{StoreAppDetails, StoreClient};
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+fn labels() -> Vec<(u32, String, Vec<String>)> {
+    vec![
+        (
+            1145360,
+            "Hades".to_string(),
+            vec!["Action".to_string(), "Roguelike".to_string()],
+        ),
+        (
+            646570,
+            "Slay the Spire".to_string(),
+            vec!["Strategy".to_string(), "Roguelike".to_string()],
+        ),
+        (
+            570,
+            "Dota 2".to_string(),
+            vec!["Strategy".to_string(), "Free to Play".to_string()],
+        ),
+    ]
+}
+
+#[test]
+fn test_genre_filter_matches_loosely() {
+    let filter = GenreFilter::from_labels("free-to-play", &labels()).expect("Genre not found");
+    assert_eq!(filter.genre, "Free to Play");
+    assert_eq!(filter.contains(570), Some(true));
+    assert_eq!(filter.contains(1145360), Some(false));
+    assert_eq!(filter.contains(42), None);
+
+    let filter = GenreFilter::from_labels("ROGUELIKE", &labels()).expect("Genre not found");
+    assert_eq!(filter.genre, "Roguelike");
+    assert_eq!(filter.contains(646570), Some(true));
+}
+
+#[test]
+fn test_unknown_genre_suggests_close_matches() {
+    let suggestions = GenreFilter::from_labels("roguelite", &labels()).err();
+    assert_eq!(suggestions, Some(vec!["Roguelike".to_string()]));
+
+    // Nothing close: the most common genres instead
+    let suggestions = GenreFilter::from_labels("dating sim", &labels()).err();
+    assert_eq!(
+        suggestions,
+        Some(vec![
+            "Roguelike".to_string(),
+            "Strategy".to_string(),
+            "Action".to_string(),
+            "Free to Play".to_string(),
+        ])
+    );
+}
+
+#[test]
+fn test_complete_genres() {
+    let counts = label_counts(&labels());
+    assert_eq!(complete_genres("str", &counts), vec!["Strategy"]);
+    assert_eq!(complete_genres("ike", &counts), vec!["Roguelike"]);
+    assert_eq!(complete_genres("", &counts).len(), 4);
+}
+
+#[tokio::test]
+async fn test_verify_fetches_uncached_apps() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let mock_server = MockServer::start().await;
+    let cached_appid = generate_test_appid();
+    let uncached_appid = generate_test_appid();
+    let delisted_appid = generate_test_appid();
+
+    db::store_app_details(
+        &connection,
+        &StoreAppDetails {
+            appid: cached_appid,
+            name: format!("Cached Roguelike {}", cached_appid),
+            available: true,
+            genres: vec!["Indie".to_string()],
+            tags: vec![("Roguelike".to_string(), 500)],
+            ..Default::default()
+        },
+    )
+    .await
+    .expect("Failed to store app details");
+
+    Mock::given(method("GET"))
+        .and(path("/api/appdetails"))
+        .and(query_param("appids", uncached_appid.to_string()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            uncached_appid.to_string(): {
+                "success": true,
+                "data": {
+                    "name": "Not A Roguelike",
+                    "genres": [{ "id": "2", "description": "Strategy" }]
+                }
+            }
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/appdetails"))
+        .and(query_param("appids", delisted_appid.to_string()))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            delisted_appid.to_string(): { "success": false }
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api.php"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({ "tags": [] })))
+        .mount(&mock_server)
+        .await;
+
+    let store = StoreClient::new(&mock_server.uri(), &mock_server.uri());
+    let filter = GenreFilter::load(&connection, "roguelike", &store)
+        .await
+        .expect("Failed to load genres")
+        .expect("Genre not found");
+
+    assert!(
+        filter
+            .verify(&connection, "anything", Some(cached_appid))
+            .await
+    );
+    assert!(
+        filter
+            .verify(
+                &connection,
+                &format!("Cached Roguelike {}", cached_appid),
+                None
+            )
+            .await
+    );
+    assert!(!filter.verify(&connection, "Unknown Game", None).await);
+    // The second lookup is answered from `store_apps`; the mock only answers once
+    for _ in 0..2 {
+        assert!(
+            !filter
+                .verify(&connection, "Not A Roguelike", Some(uncached_appid))
+                .await
+        );
+    }
+    for _ in 0..2 {
+        assert!(
+            !filter
+                .verify(&connection, "Delisted Game", Some(delisted_appid))
+                .await
+        );
+    }
+
+    // The fetched app is now cached for the next lookup
+    let genres = db::get_app_genres(&connection, &[uncached_appid])
+        .await
+        .expect("Failed to read genres");
+    assert_eq!(genres[&uncached_appid], vec!["Strategy".to_string()]);
+}
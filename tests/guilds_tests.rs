@@ -0,0 +1,188 @@
+mod common;
+
+use common::{database_url, generate_test_discord_id, generate_test_steam_id};
+use game_recommender::database::db;
+use game_recommender::guilds::*;
+use game_recommender::steam::{SteamGame, SteamOwnedGames};
+
+#[test]
+fn test_settings_change_from_args() {
+    assert_eq!(SettingsChange::from_args(&[]), Ok(SettingsChange::Show));
+    assert_eq!(
+        SettingsChange::from_args(&["announce", "<#123>"]),
+        Ok(SettingsChange::Announce(Some(123)))
+    );
+    assert_eq!(
+        SettingsChange::from_args(&["announce", "off"]),
+        Ok(SettingsChange::Announce(None))
+    );
+    assert_eq!(
+        SettingsChange::from_args(&["channels", "<#1>", "2"]),
+        Ok(SettingsChange::Channels(vec![1, 2]))
+    );
+    assert_eq!(
+        SettingsChange::from_args(&["channels", "all"]),
+        Ok(SettingsChange::Channels(Vec::new()))
+    );
+    assert_eq!(
+        SettingsChange::from_args(&["PERSONA", "off"]),
+        Ok(SettingsChange::Persona(false))
+    );
+    assert_eq!(
+        SettingsChange::from_args(&["prefix", "?"]),
+        Ok(SettingsChange::Prefix("?".to_string()))
+    );
+    assert_eq!(
+        SettingsChange::from_args(&["ai_cooldown", "0"]),
+        Ok(SettingsChange::AiCooldown(Some(0)))
+    );
+    assert_eq!(
+        SettingsChange::from_args(&["ai_member_limit", "5"]),
+        Ok(SettingsChange::AiMemberLimit(Some(5)))
+    );
+    assert_eq!(
+        SettingsChange::from_args(&["ai_server_limit", "Default"]),
+        Ok(SettingsChange::AiServerLimit(None))
+    );
+
+    assert!(SettingsChange::from_args(&["prefix", "toolong"]).is_err());
+    assert!(SettingsChange::from_args(&["announce", "#general"]).is_err());
+    assert!(SettingsChange::from_args(&["sync", "maybe"]).is_err());
+    assert!(SettingsChange::from_args(&["ai_member_limit", "0"]).is_err());
+    assert!(SettingsChange::from_args(&["ai_server_limit", "lots"]).is_err());
+    assert!(SettingsChange::from_args(&["ai_cooldown", "-1"]).is_err());
+    assert!(SettingsChange::from_args(&["ai_cooldown", "86401"]).is_err());
+    assert_eq!(
+        SettingsChange::from_args(&["volume", "11"]),
+        Err(SETTINGS_HELP.to_string())
+    );
+}
+
+#[test]
+fn test_settings_apply_and_describe() {
+    let mut settings = GuildSettings::new(1);
+    assert!(settings.allows_channel(42));
+
+    settings.apply(SettingsChange::AddChannel(10));
+    settings.apply(SettingsChange::AddChannel(10));
+    settings.apply(SettingsChange::Announce(Some(20)));
+    settings.apply(SettingsChange::Sync(false));
+    settings.apply(SettingsChange::AiMemberLimit(Some(3)));
+    assert_eq!(settings.allowed_channel_ids, vec![10]);
+    assert!(settings.allows_channel(10));
+    assert!(!settings.allows_channel(42));
+
+    let description = settings.describe();
+    assert!(description.contains("**Announcements:** <#20>"));
+    assert!(description.contains("**Command channels:** <#10>"));
+    assert!(description.contains("**Daily library sync:** off"));
+    assert!(description
+        .contains("**AI limits:** 30s between requests, 3 per member and 200 per server a day"));
+
+    settings.apply(SettingsChange::Channels(Vec::new()));
+    assert!(settings.allows_channel(42));
+}
+
+#[tokio::test]
+async fn test_guild_settings_round_trip() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let guild_id = generate_test_discord_id();
+
+    assert_eq!(
+        db::get_guild_settings(&connection, guild_id)
+            .await
+            .expect("Failed to load settings"),
+        None
+    );
+    db::ensure_guild(&connection, guild_id)
+        .await
+        .expect("Failed to create guild");
+    let mut settings = db::get_guild_settings(&connection, guild_id)
+        .await
+        .expect("Failed to load settings")
+        .expect("Guild row missing");
+    assert_eq!(settings, GuildSettings::new(guild_id));
+
+    settings.apply(SettingsChange::Channels(vec![5, 6]));
+    settings.apply(SettingsChange::Prefix("?".to_string()));
+    settings.apply(SettingsChange::Persona(false));
+    settings.apply(SettingsChange::AiCooldown(Some(5)));
+    settings.apply(SettingsChange::AiServerLimit(Some(50)));
+    db::save_guild_settings(&connection, &settings)
+        .await
+        .expect("Failed to save settings");
+
+    // Joining again must not reset anything
+    db::ensure_guild(&connection, guild_id)
+        .await
+        .expect("Failed to create guild");
+    let saved = db::get_guild_settings(&connection, guild_id)
+        .await
+        .expect("Failed to load settings");
+    assert_eq!(saved, Some(settings));
+}
+
+#[tokio::test]
+async fn test_server_games_and_sync_ids_follow_guilds() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let synced_guild = generate_test_discord_id();
+    let unsynced_guild = generate_test_discord_id();
+
+    let mut steam_ids = Vec::new();
+    for guilds in [
+        vec![synced_guild],
+        vec![unsynced_guild],
+        vec![],
+        vec![synced_guild, unsynced_guild],
+    ] {
+        let discord_id = generate_test_discord_id();
+        let steam_id = generate_test_steam_id();
+        db::link_steam(&connection, "member", discord_id, &steam_id)
+            .await
+            .expect("Failed to link user");
+        db::store_steam_games(
+            &connection,
+            &steam_id,
+            SteamOwnedGames {
+                games: vec![SteamGame {
+                    appid: 570,
+                    name: "Dota 2".to_string(),
+                    playtime_forever: 60,
+                    ..Default::default()
+                }],
+            },
+        )
+        .await
+        .expect("Failed to store games");
+        for guild_id in guilds {
+            db::add_guild_member(&connection, guild_id, discord_id)
+                .await
+                .expect("Failed to add guild member");
+        }
+        steam_ids.push(steam_id);
+    }
+
+    let mut settings = GuildSettings::new(unsynced_guild);
+    settings.apply(SettingsChange::Sync(false));
+    db::save_guild_settings(&connection, &settings)
+        .await
+        .expect("Failed to save settings");
+
+    let rows = db::get_server_user_games(&connection, Some(synced_guild))
+        .await
+        .expect("Failed to load server games");
+    let mut members: Vec<&String> = rows.iter().map(|(steam_id, _)| steam_id).collect();
+    members.sort();
+    let mut expected = vec![&steam_ids[0], &steam_ids[3]];
+    expected.sort();
+    assert_eq!(members, expected);
+
+    // Only the member of nothing but the unsynced guild is skipped
+    let sync_ids = db::get_sync_steam_ids(&connection)
+        .await
+        .expect("Failed to load sync ids");
+    assert!(sync_ids.contains(&steam_ids[0]));
+    assert!(!sync_ids.contains(&steam_ids[1]));
+    assert!(sync_ids.contains(&steam_ids[2]));
+    assert!(sync_ids.contains(&steam_ids[3]));
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::{database_url, generate_test_appid, generate_test_discord_id, generate_test_steam_id};
+use game_recommender::database::db;
+use game_recommender::leaderboard::*;
+use game_recommender::steam::{SteamGame, SteamOwnedGames};
+
+fn entries(count: usize) -> Vec<LeaderboardEntry> {
+    (0..count)
+        .map(|i| LeaderboardEntry {
+            discord_id: 100 + i as i64,
+            minutes: 6000 - i as i64 * 60,
+        })
+        .collect()
+}
+
+#[test]
+fn test_pages() {
+    assert_eq!(page_count(0), 1);
+    assert_eq!(page_count(PAGE_SIZE), 1);
+    assert_eq!(page_count(PAGE_SIZE + 1), 2);
+
+    let board = entries(12);
+    let first = render_page(&board, 0);
+    assert_eq!(first.lines().count(), PAGE_SIZE);
+    assert!(first.starts_with("🥇 <@100> — 100h\n🥈 <@101> — 99h\n🥉 <@102> — 98h\n**4.** <@103>"));
+
+    let second = render_page(&board, 1);
+    assert_eq!(second, "**11.** <@110> — 90h\n**12.** <@111> — 89h");
+    assert_eq!(render_page(&board, 2), "");
+}
+
+#[test]
+fn test_find_game() {
+    let games = vec![
+        (1145360, "Hades".to_string(), 3),
+        (1145350, "Hades II".to_string(), 1),
+        (292030, "The Witcher® 3: Wild Hunt".to_string(), 2),
+        (646570, "Slay the Spire".to_string(), 4),
+    ];
+    let find = |query: &str| find_game(query, &games).map(|g| g.0);
+
+    assert_eq!(find("hades"), Some(1145360));
+    assert_eq!(find("Hades 2"), Some(1145350));
+    assert_eq!(find("witcher 3"), Some(292030));
+    assert_eq!(find("spire"), Some(646570));
+    assert_eq!(find("slay the spyre"), Some(646570));
+    assert_eq!(find("minecraft"), None);
+    assert_eq!(find(""), None);
+
+    assert_eq!(complete_games("ha", &games), vec!["Hades", "Hades II"]);
+}
+
+#[tokio::test]
+async fn test_leaderboards_respect_opt_out() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let appid = generate_test_appid();
+    // Only owned by the hidden member
+    let hidden_appid = generate_test_appid();
+    let guild_id = generate_test_discord_id();
+
+    // The last player is in another server and never shows up here
+    let mut members = Vec::new();
+    for (minutes, hidden, in_guild) in [
+        (300, false, true),
+        (900, false, true),
+        (600, true, true),
+        (1200, false, false),
+    ] {
+        let discord_id = generate_test_discord_id();
+        let steam_id = generate_test_steam_id();
+        db::link_steam(&connection, "member", discord_id, &steam_id)
+            .await
+            .expect("Failed to link user");
+        if in_guild {
+            db::add_guild_member(&connection, guild_id, discord_id)
+                .await
+                .expect("Failed to add guild member");
+        }
+        let mut games = vec![SteamGame {
+            appid,
+            name: format!("Leaderboard Game {}", appid),
+            playtime_forever: minutes,
+            ..Default::default()
+        }];
+        if hidden {
+            games.push(SteamGame {
+                appid: hidden_appid,
+                name: format!("Hidden Game {}", hidden_appid),
+                playtime_forever: minutes,
+                ..Default::default()
+            });
+        }
+        db::store_steam_games(&connection, &steam_id, SteamOwnedGames { games })
+            .await
+            .expect("Failed to store games");
+        if hidden {
+            assert!(db::set_leaderboard_opt_out(&connection, discord_id, true)
+                .await
+                .expect("Failed to opt out"));
+        }
+        members.push(discord_id);
+    }
+
+    let board = db::get_game_playtime_leaderboard(&connection, guild_id, appid)
+        .await
+        .expect("Failed to build game leaderboard");
+    assert_eq!(
+        board,
+        vec![
+            LeaderboardEntry {
+                discord_id: members[1],
+                minutes: 900,
+            },
+            LeaderboardEntry {
+                discord_id: members[0],
+                minutes: 300,
+            },
+        ]
+    );
+
+    let total = db::get_total_playtime_leaderboard(&connection, guild_id)
+        .await
+        .expect("Failed to build total leaderboard");
+    let ranked: Vec<i64> = total.iter().map(|e| e.discord_id).collect();
+    assert_eq!(ranked, vec![members[1], members[0]]);
+
+    let games = db::get_owned_game_names(&connection, guild_id)
+        .await
+        .expect("Failed to load game names");
+    // Hidden members' games are neither suggested nor counted
+    assert!(games.contains(&(appid, format!("Leaderboard Game {}", appid), 2)));
+    assert!(games.iter().all(|(appid, _, _)| *appid != hidden_appid));
+
+    // Unlinked members can't opt out
+    assert!(!db::set_leaderboard_opt_out(&connection, -1, true)
+        .await
+        .expect("Failed to run opt out"));
+}
@@ -0,0 +1,218 @@
+mod common;
+
+use common::{database_url, generate_test_discord_id};
+use game_recommender::database::db;
+use game_recommender::guilds::{GuildSettings, SettingsChange};
+use game_recommender::quota::*;
+use std::time::Duration;
+
+/// Limits that only the test's own requests can reach
+fn test_limits() -> LlmLimits {
+    LlmLimits {
+        cooldown_secs: 0,
+        member_daily_limit: 2,
+        guild_daily_limit: 3,
+        daily_token_budget: i64::MAX,
+    }
+}
+
+#[test]
+fn test_format_wait() {
+    assert_eq!(format_wait(Duration::ZERO), "1 second");
+    assert_eq!(format_wait(Duration::from_millis(44_200)), "45 seconds");
+    assert_eq!(format_wait(Duration::from_secs(60)), "1 minute");
+    assert_eq!(format_wait(Duration::from_secs(61)), "2 minutes");
+    assert_eq!(format_wait(Duration::from_secs(119 * 60)), "119 minutes");
+    assert_eq!(format_wait(Duration::from_secs(2 * 60 * 60)), "2 hours");
+    assert_eq!(
+        format_wait(Duration::from_secs(23 * 60 * 60 + 1)),
+        "24 hours"
+    );
+}
+
+#[test]
+fn test_quota_messages() {
+    let cooldown = QuotaExceeded {
+        limit: QuotaLimit::Cooldown,
+        retry_after: Duration::from_secs(12),
+    };
+    assert_eq!(
+        cooldown.message("`!recommend --engine cf`"),
+        "⏳ Slow down a little! You can ask the AI again in 12 seconds."
+    );
+
+    let daily = QuotaExceeded {
+        limit: QuotaLimit::GuildDaily,
+        retry_after: Duration::from_secs(30 * 60),
+    };
+    let message = daily.message("`/recommend engine:cf`");
+    assert!(message.contains("This server has used all of its AI requests for today"));
+    assert!(message.contains("Try again in 30 minutes"));
+    assert!(message.contains("or use `/recommend engine:cf`, which"));
+}
+
+#[test]
+fn test_limits_for_guild() {
+    let limits = LlmLimits::default();
+    assert_eq!(limits.for_guild(None), limits);
+
+    let mut settings = GuildSettings::new(1);
+    assert_eq!(limits.for_guild(Some(&settings)), limits);
+
+    settings.apply(SettingsChange::AiCooldown(Some(0)));
+    settings.apply(SettingsChange::AiServerLimit(Some(1_000)));
+    assert_eq!(
+        limits.for_guild(Some(&settings)),
+        LlmLimits {
+            cooldown_secs: 0,
+            member_daily_limit: DEFAULT_LLM_MEMBER_DAILY_LIMIT,
+            guild_daily_limit: 1_000,
+            daily_token_budget: DEFAULT_LLM_DAILY_TOKEN_BUDGET,
+        }
+    );
+}
+
+#[tokio::test]
+async fn test_claim_llm_quota_cooldown() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let discord_id = generate_test_discord_id();
+    let limits = LlmLimits {
+        cooldown_secs: 60,
+        ..test_limits()
+    };
+
+    db::claim_llm_quota(&connection, discord_id, None, &limits, 10)
+        .await
+        .expect("Failed to claim quota")
+        .expect("First request was refused");
+    let exceeded = db::claim_llm_quota(&connection, discord_id, None, &limits, 10)
+        .await
+        .expect("Failed to claim quota")
+        .expect_err("Request during the cooldown was allowed");
+    assert_eq!(exceeded.limit, QuotaLimit::Cooldown);
+    assert!(exceeded.retry_after > Duration::from_secs(50));
+    assert!(exceeded.retry_after <= Duration::from_secs(60));
+
+    // Someone else isn't affected by the cooldown
+    db::claim_llm_quota(&connection, generate_test_discord_id(), None, &limits, 10)
+        .await
+        .expect("Failed to claim quota")
+        .expect("Another member was refused");
+}
+
+#[tokio::test]
+async fn test_refunded_llm_quota_no_longer_counts() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let discord_id = generate_test_discord_id();
+    let limits = LlmLimits {
+        cooldown_secs: 60,
+        ..test_limits()
+    };
+
+    let usage_id = db::claim_llm_quota(&connection, discord_id, None, &limits, 10)
+        .await
+        .expect("Failed to claim quota")
+        .expect("First request was refused");
+    db::refund_llm_quota(&connection, usage_id)
+        .await
+        .expect("Failed to refund quota");
+
+    // The failed request neither started a cooldown nor used up a daily request
+    for _ in 0..limits.member_daily_limit {
+        let usage_id = db::claim_llm_quota(&connection, discord_id, None, &test_limits(), 10)
+            .await
+            .expect("Failed to claim quota")
+            .expect("Request after a refund was refused");
+        assert!(usage_id > 0);
+    }
+}
+
+#[tokio::test]
+async fn test_claim_llm_quota_daily_limits() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let guild_id = generate_test_discord_id();
+    let first = generate_test_discord_id();
+    let second = generate_test_discord_id();
+    let limits = test_limits();
+
+    for _ in 0..2 {
+        db::claim_llm_quota(&connection, first, Some(guild_id), &limits, 10)
+            .await
+            .expect("Failed to claim quota")
+            .expect("Request under the limit was refused");
+    }
+    let exceeded = db::claim_llm_quota(&connection, first, Some(guild_id), &limits, 10)
+        .await
+        .expect("Failed to claim quota")
+        .expect_err("Request over the member limit was allowed");
+    assert_eq!(exceeded.limit, QuotaLimit::MemberDaily);
+    assert!(exceeded.retry_after > Duration::from_secs(23 * 60 * 60));
+    assert!(exceeded.retry_after <= Duration::from_secs(24 * 60 * 60));
+
+    // The refused request didn't count, so the guild has exactly one request left
+    db::claim_llm_quota(&connection, second, Some(guild_id), &limits, 10)
+        .await
+        .expect("Failed to claim quota")
+        .expect("Request under the guild limit was refused");
+    let exceeded = db::claim_llm_quota(&connection, second, Some(guild_id), &limits, 10)
+        .await
+        .expect("Failed to claim quota")
+        .expect_err("Request over the guild limit was allowed");
+    assert_eq!(exceeded.limit, QuotaLimit::GuildDaily);
+
+    // The same member is still welcome in DMs until their own limit is hit
+    db::claim_llm_quota(&connection, second, None, &limits, 10)
+        .await
+        .expect("Failed to claim quota")
+        .expect("DM request under the member limit was refused");
+}
+
+#[tokio::test]
+async fn test_claim_llm_quota_token_budget() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let discord_id = generate_test_discord_id();
+
+    db::claim_llm_quota(&connection, discord_id, None, &test_limits(), 10)
+        .await
+        .expect("Failed to claim quota")
+        .expect("Request under the budget was refused");
+
+    // Every recent request counts towards the global budget, so one that's already been
+    // spent is enough to go over this one
+    let limits = LlmLimits {
+        daily_token_budget: 10,
+        ..test_limits()
+    };
+    let exceeded = db::claim_llm_quota(&connection, generate_test_discord_id(), None, &limits, 1)
+        .await
+        .expect("Failed to claim quota")
+        .expect_err("Request over the token budget was allowed");
+    assert_eq!(exceeded.limit, QuotaLimit::TokenBudget);
+    assert!(exceeded.retry_after <= Duration::from_secs(24 * 60 * 60));
+}
+
+#[tokio::test]
+async fn test_settle_llm_usage_records_reported_tokens() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+
+    let usage_id = db::claim_llm_quota(
+        &connection,
+        generate_test_discord_id(),
+        None,
+        &test_limits(),
+        ESTIMATED_TOKENS_PER_REQUEST,
+    )
+    .await
+    .expect("Failed to claim quota")
+    .expect("Request under the budget was refused");
+    db::settle_llm_usage(&connection, usage_id, 734)
+        .await
+        .expect("Failed to settle usage");
+
+    let tokens: i32 = sqlx::query_scalar("SELECT tokens FROM llm_usage WHERE id = $1;")
+        .bind(usage_id)
+        .fetch_one(&connection)
+        .await
+        .unwrap();
+    assert_eq!(tokens, 734);
+}
@@ -0,0 +1,217 @@
+mod common;
+
+use chrono::Utc;
+use common::{database_url, generate_test_steam_id, link_test_user};
+use game_recommender::database::db;
+use game_recommender::stats::*;
+use game_recommender::steam::{SteamGame, SteamOwnedGames};
+use std::collections::HashMap;
+
+fn game(appid: u32, name: &str, playtime_forever: u32, playtime_2weeks: Option<u32>) -> SteamGame {
+    SteamGame {
+        appid,
+        name: name.to_string(),
+        playtime_forever,
+        playtime_2weeks,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_format_hours() {
+    assert_eq!(format_hours(0), "0m");
+    assert_eq!(format_hours(45), "45m");
+    assert_eq!(format_hours(90), "1.5h");
+    assert_eq!(format_hours(593), "9.9h");
+    assert_eq!(format_hours(599), "10h");
+    assert_eq!(format_hours(600), "10h");
+    assert_eq!(format_hours(74_040), "1,234h");
+    assert_eq!(format_hours(60_000_000), "1,000,000h");
+}
+
+#[test]
+fn test_format_percent_and_count() {
+    assert_eq!(format_percent(0, 0), "0%");
+    assert_eq!(format_percent(1, 2), "50%");
+    assert_eq!(format_percent(1, 3), "33.3%");
+    assert_eq!(format_percent(2, 3), "66.7%");
+    assert_eq!(format_percent(999, 1000), "99.9%");
+    assert_eq!(format_percent(9999, 10000), "100%");
+
+    assert_eq!(format_count(1, "game"), "1 game");
+    assert_eq!(format_count(0, "game"), "0 games");
+    assert_eq!(format_count(1204, "game"), "1,204 games");
+}
+
+#[test]
+fn test_library_stats_fields() {
+    let games = vec![
+        game(1, "Hades", 3000, Some(120)),
+        game(2, "Slay the Spire", 6000, Some(30)),
+        game(3, "Unplayed", 0, None),
+        game(4, "Also Unplayed", 0, None),
+    ];
+    let genres = HashMap::from([
+        (1, vec!["Action".to_string(), "Indie".to_string()]),
+        (2, vec!["Strategy".to_string(), "Indie".to_string()]),
+    ]);
+    let gains = vec![
+        (1, "Hades".to_string(), 120),
+        (2, "Slay the Spire".to_string(), 30),
+    ];
+
+    let stats = LibraryStats::new(&games, &genres, &gains);
+    assert_eq!(stats.library_size, 4);
+    assert_eq!(stats.total_minutes, 9000);
+    assert_eq!(stats.never_played, 2);
+    assert_eq!(stats.recent_minutes, 150);
+    assert_eq!(stats.most_improved, Some(("Hades".to_string(), 120)));
+
+    let fields = stats.fields();
+    let field = |label: &str| {
+        fields
+            .iter()
+            .find(|(name, _)| name == label)
+            .map(|(_, value)| value.as_str())
+    };
+    assert_eq!(field("Total playtime"), Some("150h"));
+    assert_eq!(field("Library"), Some("4 games"));
+    assert_eq!(field("Pile of shame"), Some("2 games never played (50%)"));
+    assert_eq!(field("Last 2 weeks"), Some("2.5h"));
+    assert_eq!(
+        field("Top genres"),
+        Some("Indie (150h), Strategy (100h), Action (50h)")
+    );
+    assert_eq!(
+        field("Most improved since last sync"),
+        Some("Hades (+2.0h)")
+    );
+}
+
+#[test]
+fn test_library_stats_without_history() {
+    let stats = LibraryStats::new(&[game(1, "Unplayed", 0, None)], &HashMap::new(), &[]);
+    let labels: Vec<String> = stats.fields().into_iter().map(|(name, _)| name).collect();
+    assert_eq!(
+        labels,
+        vec!["Total playtime", "Library", "Pile of shame", "Last 2 weeks"]
+    );
+    assert_eq!(stats.fields()[2].1, "1 game never played (100%)");
+}
+
+#[test]
+fn test_format_age() {
+    assert_eq!(format_age(chrono::Duration::seconds(20)), "just now");
+    assert_eq!(format_age(chrono::Duration::minutes(1)), "1 minute ago");
+    assert_eq!(format_age(chrono::Duration::minutes(59)), "59 minutes ago");
+    assert_eq!(format_age(chrono::Duration::minutes(150)), "2 hours ago");
+    assert_eq!(format_age(chrono::Duration::days(3)), "3 days ago");
+}
+
+#[test]
+fn test_sync_changes() {
+    let before = vec![game(1, "Hades", 600, None), game(2, "Celeste", 0, None)];
+    let after = vec![
+        game(1, "Hades", 690, None),
+        game(2, "Celeste", 0, None),
+        game(3, "Balatro", 30, None),
+    ];
+
+    let changes = SyncChanges::between(&before, &after);
+    assert_eq!(
+        changes,
+        SyncChanges {
+            games_added: 1,
+            minutes_played: 120,
+        }
+    );
+    assert_eq!(
+        changes.summary(),
+        "1 new game added, 2.0h played since your last sync."
+    );
+    assert_eq!(
+        SyncChanges::between(&after, &after).summary(),
+        "Nothing changed since your last sync."
+    );
+}
+
+#[tokio::test]
+async fn test_last_sync_gains() {
+    let test_steam_id = generate_test_steam_id();
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    link_test_user(&connection, &test_steam_id).await;
+
+    for playtimes in [[100, 200], [160, 200], [160, 230]] {
+        db::store_steam_games(
+            &connection,
+            &test_steam_id,
+            SteamOwnedGames {
+                games: vec![
+                    game(1, "First", playtimes[0], None),
+                    game(2, "Second", playtimes[1], None),
+                ],
+            },
+        )
+        .await
+        .expect("Failed to store games");
+    }
+
+    // Only the latest sync counts, not the 60 minutes gained the sync before
+    let gains = db::get_last_sync_gains(&connection, &test_steam_id)
+        .await
+        .expect("Failed to fetch gains");
+    assert_eq!(gains, vec![(2, "Second".to_string(), 30)]);
+}
+
+#[tokio::test]
+async fn test_games_last_updated() {
+    let test_steam_id = generate_test_steam_id();
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    link_test_user(&connection, &test_steam_id).await;
+
+    let before = db::get_games_last_updated(&connection, &test_steam_id)
+        .await
+        .expect("Failed to fetch last update");
+    assert_eq!(before, None);
+
+    db::store_steam_games(
+        &connection,
+        &test_steam_id,
+        SteamOwnedGames {
+            games: vec![game(1, "First", 100, None)],
+        },
+    )
+    .await
+    .expect("Failed to store games");
+
+    let synced_at = db::get_games_last_updated(&connection, &test_steam_id)
+        .await
+        .expect("Failed to fetch last update")
+        .expect("Stored games should have a sync time");
+    let age = Utc::now() - synced_at;
+    assert!(age < chrono::Duration::minutes(1), "synced {:?} ago", age);
+    assert!(age > chrono::Duration::seconds(-5), "synced {:?} ago", age);
+}
+
+#[tokio::test]
+async fn test_empty_library_records_sync_time() {
+    let test_steam_id = generate_test_steam_id();
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    link_test_user(&connection, &test_steam_id).await;
+
+    // A library without games still counts as freshly synced, so it isn't refetched on
+    // every read
+    db::store_steam_games(
+        &connection,
+        &test_steam_id,
+        SteamOwnedGames { games: Vec::new() },
+    )
+    .await
+    .expect("Failed to store games");
+
+    let synced_at = db::get_games_last_updated(&connection, &test_steam_id)
+        .await
+        .expect("Failed to fetch last update")
+        .expect("An empty library should have a sync time");
+    assert!(Utc::now() - synced_at < chrono::Duration::minutes(1));
+}
@@ -1,40 +1,33 @@
-use dotenvy::dotenv;
+mod common;
+
+use chrono::Utc;
+use common::{database_url, generate_test_discord_id, generate_test_steam_id, link_test_user};
 use game_recommender::database::db;
+use game_recommender::rate_limit::TokenBucket;
 use game_recommender::steam::*;
-use std::env;
-use uuid::Uuid;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use wiremock::matchers::{method, path, query_param};
 use wiremock::{Mock, MockServer, ResponseTemplate};
 
-/// Fetch the DATABASE_TEST_URL once
-fn database_url() -> String {
-    dotenv().ok();
-    env::var("DATABASE_TEST_URL").expect("DATABASE_TEST_URL not set")
-}
-
-/// Helper to generate random steam id for testing
-fn generate_test_steam_id() -> String {
-    format!("test_steam_{}", Uuid::new_v4())
-}
-
-/// Helper to generate random discord id for testing
-fn generate_test_discord_id() -> i64 {
-    rand::random::<i64>() % 1_000_000
-}
-
 #[tokio::test]
 async fn test_mocked_fetch_steam_games() {
     let mock_server = MockServer::start().await;
+    let steam = SteamClient::new(&mock_server.uri(), "test_api_key");
 
     // Mock response data
     let mock_games = vec![
         SteamGame {
+            appid: 10,
             name: "Test Game 1".to_string(),
             playtime_forever: 600,
+            ..Default::default()
         },
         SteamGame {
+            appid: 20,
             name: "Test Game 2".to_string(),
             playtime_forever: 1200,
+            ..Default::default()
         },
     ];
 
@@ -51,12 +44,13 @@ async fn test_mocked_fetch_steam_games() {
         .await;
 
     // Use the mock server instead of the real Steam API
-    let result = fetch_steam_games(&mock_server.uri(), "test_steam_id_12345", "test_api_key").await;
+    let result = steam.fetch_games("test_steam_id_12345").await;
 
     assert!(result.is_ok());
     let games = result.unwrap();
     assert_eq!(games.len(), mock_games.len());
     assert_eq!(games[0].name, "Test Game 1");
+    assert_eq!(games[0].appid, 10);
     assert_eq!(games[1].playtime_forever, 1200);
 }
 
@@ -75,7 +69,9 @@ async fn test_link_and_get_steam_id() {
         .expect("Failed to link steam");
 
     // Retrieve the steam ID and check
-    let retrieved_id = db::get_steam_id(&connection, test_discord_id).await.unwrap();
+    let retrieved_id = db::get_steam_id(&connection, test_discord_id)
+        .await
+        .unwrap();
     assert_eq!(retrieved_id, Some(test_steam_id.to_string()));
 }
 
@@ -85,16 +81,21 @@ async fn test_populate_database_with_games() {
     let test_discord_id = generate_test_discord_id();
     let test_username = "test_username";
     let mock_server = MockServer::start().await;
+    let steam = SteamClient::new(&mock_server.uri(), "test_api_key");
 
     // Prepare mock response data for the Steam API
     let mock_games = vec![
         SteamGame {
+            appid: 10,
             name: "Test Game 1".to_string(),
             playtime_forever: 600,
+            ..Default::default()
         },
         SteamGame {
+            appid: 20,
             name: "Test Game 2".to_string(),
             playtime_forever: 1200,
+            ..Default::default()
         },
     ];
     let response = serde_json::json!({
@@ -119,7 +120,8 @@ async fn test_populate_database_with_games() {
         .expect("Failed to link steam");
 
     // Fetch Steam games from the API.
-    let games_vec = fetch_steam_games(&mock_server.uri(), &test_steam_id, "test_api_key")
+    let games_vec = steam
+        .fetch_games(&test_steam_id)
         .await
         .expect("Failed to fetch steam games");
 
@@ -147,16 +149,21 @@ async fn test_fetch_user_games() {
     let test_discord_id = generate_test_discord_id();
     let test_username = "test_username";
     let mock_server = MockServer::start().await;
+    let steam = SteamClient::new(&mock_server.uri(), "test_api_key");
 
     // Prepare mock response data for the Steam API
     let mock_games = vec![
         SteamGame {
+            appid: 10,
             name: "Test Game 1".to_string(),
             playtime_forever: 600,
+            ..Default::default()
         },
         SteamGame {
+            appid: 20,
             name: "Test Game 2".to_string(),
             playtime_forever: 1200,
+            ..Default::default()
         },
     ];
     let response = serde_json::json!({
@@ -181,7 +188,8 @@ async fn test_fetch_user_games() {
         .expect("Failed to link steam");
 
     // Fetch Steam games
-    let games_vec = fetch_steam_games(&mock_server.uri(), &test_steam_id, "test_api_key")
+    let games_vec = steam
+        .fetch_games(&test_steam_id)
         .await
         .expect("Failed to fetch steam games");
 
@@ -202,11 +210,14 @@ async fn test_fetch_user_games() {
 async fn test_game_data_update_behavior() {
     let test_steam_id = generate_test_steam_id();
     let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    link_test_user(&connection, &test_steam_id).await;
 
     // Insert initial game data.
     let initial_game = SteamGame {
+        appid: 30,
         name: "New Game".to_string(),
         playtime_forever: 600,
+        ..Default::default()
     };
     let owned_games = SteamOwnedGames {
         games: vec![initial_game.clone()],
@@ -217,8 +228,10 @@ async fn test_game_data_update_behavior() {
 
     // Update the same game with new playtime.
     let updated_game = SteamGame {
+        appid: 30,
         name: "New Game".to_string(),
         playtime_forever: 1200,
+        ..Default::default()
     };
     let updated_owned_games = SteamOwnedGames {
         games: vec![updated_game.clone()],
@@ -236,3 +249,375 @@ async fn test_game_data_update_behavior() {
         "Game playtime should be updated to 1200"
     );
 }
+
+#[tokio::test]
+async fn test_renamed_game_updates_in_place() {
+    let test_steam_id = generate_test_steam_id();
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    link_test_user(&connection, &test_steam_id).await;
+
+    let original = SteamGame {
+        appid: 40,
+        name: "Old Title".to_string(),
+        playtime_forever: 600,
+        ..Default::default()
+    };
+    db::store_steam_games(
+        &connection,
+        &test_steam_id,
+        SteamOwnedGames {
+            games: vec![original],
+        },
+    )
+    .await
+    .expect("Failed to store initial game");
+
+    // Same appid under a new name, as happens when a publisher renames a title
+    let renamed = SteamGame {
+        appid: 40,
+        name: "New Title".to_string(),
+        playtime_forever: 900,
+        img_icon_url: Some("abc123".to_string()),
+        playtime_2weeks: Some(300),
+        rtime_last_played: Some(1_700_000_000),
+    };
+    db::store_steam_games(
+        &connection,
+        &test_steam_id,
+        SteamOwnedGames {
+            games: vec![renamed],
+        },
+    )
+    .await
+    .expect("Failed to store renamed game");
+
+    let games = db::get_user_games(&connection, &test_steam_id)
+        .await
+        .expect("Failed to fetch games");
+    assert_eq!(games.len(), 1, "Renamed game should not be duplicated");
+    assert_eq!(games[0].appid, 40);
+    assert_eq!(games[0].name, "New Title");
+    assert_eq!(games[0].img_icon_url.as_deref(), Some("abc123"));
+    assert_eq!(games[0].playtime_2weeks, Some(300));
+    assert_eq!(games[0].rtime_last_played, Some(1_700_000_000));
+}
+
+#[tokio::test]
+async fn test_playtime_history_records_deltas() {
+    let test_steam_id = generate_test_steam_id();
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    link_test_user(&connection, &test_steam_id).await;
+    let game = |appid: u32, name: &str, playtime_forever: u32| SteamGame {
+        appid,
+        name: name.to_string(),
+        playtime_forever,
+        ..Default::default()
+    };
+
+    // The first sync is only a baseline
+    let before = Utc::now();
+    db::store_steam_games(
+        &connection,
+        &test_steam_id,
+        SteamOwnedGames {
+            games: vec![game(10, "Played", 600), game(20, "Untouched", 300)],
+        },
+    )
+    .await
+    .expect("Failed to store initial games");
+    let after_first = Utc::now();
+    assert_eq!(
+        db::get_minutes_played(&connection, &test_steam_id, before, after_first)
+            .await
+            .expect("Failed to query minutes played"),
+        0
+    );
+
+    // 90 more minutes in one game, plus a new game played for 45 minutes
+    db::store_steam_games(
+        &connection,
+        &test_steam_id,
+        SteamOwnedGames {
+            games: vec![
+                game(10, "Played", 690),
+                game(20, "Untouched", 300),
+                game(30, "New", 45),
+            ],
+        },
+    )
+    .await
+    .expect("Failed to store updated games");
+    let now = Utc::now();
+
+    let total = db::get_minutes_played(&connection, &test_steam_id, after_first, now)
+        .await
+        .expect("Failed to query minutes played");
+    assert_eq!(total, 135);
+
+    let by_game = db::get_minutes_played_by_game(&connection, &test_steam_id, after_first, now)
+        .await
+        .expect("Failed to query minutes played by game");
+    assert_eq!(
+        by_game,
+        vec![(10, "Played".to_string(), 90), (30, "New".to_string(), 45)]
+    );
+
+    // A window before the second sync sees none of it
+    let earlier = db::get_minutes_played(&connection, &test_steam_id, before, after_first)
+        .await
+        .expect("Failed to query minutes played");
+    assert_eq!(earlier, 0);
+}
+
+#[test]
+fn test_parse_steam_id_formats() {
+    assert_eq!(
+        parse_steam_id_input("76561197960287930"),
+        Some(SteamIdInput::SteamId64("76561197960287930".to_string()))
+    );
+    assert_eq!(
+        parse_steam_id_input("STEAM_0:0:11101"),
+        Some(SteamIdInput::SteamId64("76561197960287930".to_string()))
+    );
+    assert_eq!(
+        parse_steam_id_input("[U:1:22202]"),
+        Some(SteamIdInput::SteamId64("76561197960287930".to_string()))
+    );
+    assert_eq!(
+        parse_steam_id_input("https://steamcommunity.com/profiles/76561197960287930/"),
+        Some(SteamIdInput::SteamId64("76561197960287930".to_string()))
+    );
+    assert_eq!(
+        parse_steam_id_input("https://steamcommunity.com/id/gabelogannewell"),
+        Some(SteamIdInput::Vanity("gabelogannewell".to_string()))
+    );
+    assert_eq!(
+        parse_steam_id_input("gabelogannewell"),
+        Some(SteamIdInput::Vanity("gabelogannewell".to_string()))
+    );
+    assert_eq!(parse_steam_id_input("https://example.com/id/foo"), None);
+    assert_eq!(parse_steam_id_input(""), None);
+}
+
+#[tokio::test]
+async fn test_mocked_resolve_vanity_url() {
+    let mock_server = MockServer::start().await;
+    let steam = SteamClient::new(&mock_server.uri(), "test_api_key");
+
+    Mock::given(method("GET"))
+        .and(path("/ISteamUser/ResolveVanityURL/v0001/"))
+        .and(query_param("key", "test_api_key"))
+        .and(query_param("vanityurl", "gabelogannewell"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "response": { "steamid": "76561197960287930", "success": 1 }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let resolved = steam
+        .resolve_steam_id("https://steamcommunity.com/id/gabelogannewell/")
+        .await
+        .expect("Failed to resolve vanity URL");
+    assert_eq!(resolved, "76561197960287930");
+
+    // SteamID64 input never hits the API
+    let resolved = steam
+        .resolve_steam_id("76561197960287930")
+        .await
+        .expect("Failed to resolve SteamID64");
+    assert_eq!(resolved, "76561197960287930");
+}
+
+#[tokio::test]
+async fn test_mocked_resolve_unknown_vanity_url() {
+    let mock_server = MockServer::start().await;
+    let steam = SteamClient::new(&mock_server.uri(), "test_api_key");
+
+    Mock::given(method("GET"))
+        .and(path("/ISteamUser/ResolveVanityURL/v0001/"))
+        .and(query_param("vanityurl", "nobody_here"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "response": { "success": 42, "message": "No match" }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let result = steam.resolve_steam_id("nobody_here").await;
+    assert!(matches!(result, Err(SteamError::NotFound)));
+}
+
+#[tokio::test]
+async fn test_mocked_private_profile_is_detected() {
+    let mock_server = MockServer::start().await;
+    let steam = SteamClient::new(&mock_server.uri(), "test_api_key");
+
+    // Hidden game details come back as an empty response rather than an error
+    Mock::given(method("GET"))
+        .and(path("/IPlayerService/GetOwnedGames/v1/"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "response": {} })),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/ISteamUser/GetPlayerSummaries/v0002/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "response": { "players": [
+                { "personaname": "Hidden", "communityvisibilitystate": 1 }
+            ] }
+        })))
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+
+    let result = steam.fetch_games("76561197960287930").await;
+    assert!(matches!(result, Err(SteamError::PrivateProfile)));
+
+    let profile = steam
+        .fetch_profile("76561197960287930")
+        .await
+        .expect("Failed to fetch profile");
+    assert_eq!(profile.personaname, "Hidden");
+    assert!(!profile.is_public());
+}
+
+#[tokio::test]
+async fn test_mocked_empty_library_and_missing_profile() {
+    let mock_server = MockServer::start().await;
+    let steam = SteamClient::new(&mock_server.uri(), "test_api_key");
+
+    Mock::given(method("GET"))
+        .and(path("/IPlayerService/GetOwnedGames/v1/"))
+        .and(query_param("steamid", "76561197960287930"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "response": { "game_count": 0 }
+        })))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/IPlayerService/GetOwnedGames/v1/"))
+        .and(query_param("steamid", "76561197960287931"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!({ "response": {} })),
+        )
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/ISteamUser/GetPlayerSummaries/v0002/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "response": { "players": [] }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let games = steam
+        .fetch_games("76561197960287930")
+        .await
+        .expect("An empty library is not an error");
+    assert!(games.is_empty());
+
+    let result = steam.fetch_games("76561197960287931").await;
+    assert!(matches!(result, Err(SteamError::NotFound)));
+}
+
+#[tokio::test]
+async fn test_mocked_rejected_api_key() {
+    let mock_server = MockServer::start().await;
+    let steam = SteamClient::new(&mock_server.uri(), "bad_key");
+
+    // A bad key is not retried
+    Mock::given(method("GET"))
+        .and(path("/IPlayerService/GetOwnedGames/v1/"))
+        .respond_with(ResponseTemplate::new(403))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let result = steam.fetch_games("76561197960287930").await;
+    assert!(matches!(result, Err(SteamError::Unauthorized)));
+}
+
+#[tokio::test]
+async fn test_mocked_rate_limited_request_honors_retry_after() {
+    let mock_server = MockServer::start().await;
+    let steam = SteamClient::new(&mock_server.uri(), "test_api_key");
+
+    // The first call is throttled; the retry waits as long as Steam asked before trying again
+    Mock::given(method("GET"))
+        .and(path("/IPlayerService/GetOwnedGames/v1/"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/IPlayerService/GetOwnedGames/v1/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "response": { "game_count": 1, "games": [
+                { "appid": 10, "name": "Test Game 1", "playtime_forever": 60 }
+            ] }
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let started = Instant::now();
+    let games = steam
+        .fetch_games("76561197960287930")
+        .await
+        .expect("Failed to fetch games after the retry");
+    assert_eq!(games.len(), 1);
+    assert!(started.elapsed() >= Duration::from_secs(1));
+}
+
+#[tokio::test]
+async fn test_mocked_profile_retries_server_errors() {
+    let mock_server = MockServer::start().await;
+    let steam = SteamClient::new(&mock_server.uri(), "test_api_key");
+
+    Mock::given(method("GET"))
+        .and(path("/ISteamUser/GetPlayerSummaries/v0002/"))
+        .respond_with(ResponseTemplate::new(500).insert_header("Retry-After", "0"))
+        .up_to_n_times(2)
+        .expect(2)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/ISteamUser/GetPlayerSummaries/v0002/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "response": { "players": [
+                { "personaname": "Flaky", "communityvisibilitystate": 3 }
+            ] }
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let profile = steam
+        .fetch_profile("76561197960287930")
+        .await
+        .expect("Failed to fetch profile after retries");
+    assert_eq!(profile.personaname, "Flaky");
+    assert!(profile.is_public());
+}
+
+#[tokio::test]
+async fn test_token_bucket_limits_bursts() {
+    let bucket = Arc::new(TokenBucket::new(2, 10.0));
+
+    // Two tokens are ready at once; the next two have to wait for 100ms refills
+    let started = Instant::now();
+    let tasks: Vec<_> = (0..4)
+        .map(|_| {
+            let bucket = Arc::clone(&bucket);
+            tokio::spawn(async move { bucket.acquire().await })
+        })
+        .collect();
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    let elapsed = started.elapsed();
+    assert!(elapsed >= Duration::from_millis(190), "took {:?}", elapsed);
+    assert!(elapsed < Duration::from_secs(1), "took {:?}", elapsed);
+}
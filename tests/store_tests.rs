@@ -0,0 +1,255 @@
+mod common;
+
+use common::{database_url, generate_test_appid};
+use game_recommender::candidates::{tag_similar, AppTags};
+use game_recommender::database::db;
+use game_recommender::steam::SteamGame;
+use game_recommender::store::*;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+#[tokio::test]
+async fn test_mocked_fetch_app_details() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/appdetails"))
+        .and(query_param("appids", "1145360"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "1145360": {
+                "success": true,
+                "data": {
+                    "type": "game",
+                    "name": "Hades",
+                    "is_free": false,
+                    "genres": [
+                        { "id": "1", "description": "Action" },
+                        { "id": "23", "description": "Indie" }
+                    ],
+                    "categories": [{ "id": 2, "description": "Single-player" }],
+                    "price_overview": { "currency": "USD", "initial": 2499, "final": 2499 },
+                    "metacritic": { "score": 93, "url": "https://www.metacritic.com" },
+                    "release_date": { "coming_soon": false, "date": "17 Sep, 2020" }
+                }
+            }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let store = StoreClient::new(&mock_server.uri(), &mock_server.uri());
+    let details = store
+        .fetch_app_details(1145360)
+        .await
+        .expect("Failed to fetch app details")
+        .expect("App should have a store page");
+
+    assert_eq!(details.name, "Hades");
+    assert_eq!(details.genres, vec!["Action", "Indie"]);
+    assert_eq!(details.categories, vec!["Single-player"]);
+    assert_eq!(details.price_cents, Some(2499));
+    assert_eq!(details.currency.as_deref(), Some("USD"));
+    assert_eq!(details.metacritic_score, Some(93));
+    assert_eq!(details.release_date.as_deref(), Some("17 Sep, 2020"));
+}
+
+#[tokio::test]
+async fn test_mocked_fetch_delisted_app_details() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api/appdetails"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "12345": { "success": false }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let store = StoreClient::new(&mock_server.uri(), &mock_server.uri());
+    let details = store
+        .fetch_app_details(12345)
+        .await
+        .expect("Failed to fetch app details");
+    assert_eq!(details, None);
+}
+
+#[tokio::test]
+async fn test_mocked_store_retries_rate_limited_requests() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api.php"))
+        .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api.php"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "tags": { "Roguelike": 900 }
+        })))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+
+    let store = StoreClient::new(&mock_server.uri(), &mock_server.uri());
+    let tags = store.fetch_app_tags(1145360).await.unwrap();
+    assert_eq!(tags, vec![("Roguelike".to_string(), 900)]);
+}
+
+#[tokio::test]
+async fn test_mocked_store_errors_tell_rejected_apps_from_outages() {
+    let mock_server = MockServer::start().await;
+
+    // An app whose data doesn't parse, an app the store refuses outright, and an outage
+    Mock::given(method("GET"))
+        .and(path("/api/appdetails"))
+        .and(query_param("appids", "1"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "1": { "success": true, "data": [] }
+        })))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/appdetails"))
+        .and(query_param("appids", "2"))
+        .respond_with(ResponseTemplate::new(403))
+        .expect(1)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api/appdetails"))
+        .and(query_param("appids", "3"))
+        .respond_with(ResponseTemplate::new(503).insert_header("Retry-After", "0"))
+        .expect(3)
+        .mount(&mock_server)
+        .await;
+
+    let store = StoreClient::new(&mock_server.uri(), &mock_server.uri());
+    for appid in [1, 2] {
+        let error = store.fetch_app_details(appid).await.unwrap_err();
+        assert!(matches!(error, StoreError::Rejected(_)), "{:?}", error);
+    }
+    let error = store.fetch_app_details(3).await.unwrap_err();
+    assert!(matches!(error, StoreError::Unavailable(_)), "{:?}", error);
+}
+
+#[tokio::test]
+async fn test_mocked_fetch_app_tags() {
+    let mock_server = MockServer::start().await;
+
+    Mock::given(method("GET"))
+        .and(path("/api.php"))
+        .and(query_param("appid", "1145360"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "appid": 1145360,
+            "tags": { "Roguelike": 900, "Action": 1200, "Mythology": 300 }
+        })))
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/api.php"))
+        .and(query_param("appid", "12345"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "appid": 12345,
+            "tags": []
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let store = StoreClient::new(&mock_server.uri(), &mock_server.uri());
+    let tags = store.fetch_app_tags(1145360).await.unwrap();
+    assert_eq!(
+        tags,
+        vec![
+            ("Action".to_string(), 1200),
+            ("Roguelike".to_string(), 900),
+            ("Mythology".to_string(), 300)
+        ]
+    );
+
+    let tags = store.fetch_app_tags(12345).await.unwrap();
+    assert!(tags.is_empty());
+}
+
+#[tokio::test]
+async fn test_store_and_replace_app_details() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let appid = generate_test_appid();
+
+    let mut details = StoreAppDetails {
+        appid,
+        name: "Test Store Game".to_string(),
+        available: true,
+        genres: vec!["Action".to_string(), "Indie".to_string()],
+        categories: vec!["Single-player".to_string()],
+        tags: vec![("Roguelike".to_string(), 100)],
+        ..Default::default()
+    };
+    db::store_app_details(&connection, &details)
+        .await
+        .expect("Failed to store app details");
+
+    // A refresh replaces the genre list instead of appending to it
+    details.genres = vec!["RPG".to_string()];
+    db::store_app_details(&connection, &details)
+        .await
+        .expect("Failed to refresh app details");
+
+    let genres = db::get_app_genres(&connection, &[appid])
+        .await
+        .expect("Failed to fetch genres");
+    assert_eq!(genres.get(&appid), Some(&vec!["RPG".to_string()]));
+
+    let tags = db::get_all_app_tags(&connection)
+        .await
+        .expect("Failed to fetch tags");
+    assert_eq!(
+        tags.get(&appid),
+        Some(&(
+            "Test Store Game".to_string(),
+            vec![("Roguelike".to_string(), 100)]
+        ))
+    );
+}
+
+#[test]
+fn test_tag_similar_candidates() {
+    let tags = |list: &[(&str, u32)]| -> Vec<(String, u32)> {
+        list.iter().map(|(t, v)| (t.to_string(), *v)).collect()
+    };
+    let mut app_tags = AppTags::new();
+    app_tags.insert(
+        1,
+        (
+            "Hades".to_string(),
+            tags(&[("Roguelike", 100), ("Action", 80)]),
+        ),
+    );
+    app_tags.insert(
+        2,
+        (
+            "Dead Cells".to_string(),
+            tags(&[("Roguelike", 90), ("Metroidvania", 70)]),
+        ),
+    );
+    app_tags.insert(
+        3,
+        (
+            "Stardew Valley".to_string(),
+            tags(&[("Farming Sim", 100), ("Cozy", 90)]),
+        ),
+    );
+
+    let hades = SteamGame {
+        appid: 1,
+        name: "Hades".to_string(),
+        playtime_forever: 6000,
+        ..Default::default()
+    };
+    let candidates = tag_similar(&app_tags, &[&hades], 5);
+
+    let names: Vec<&str> = candidates.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["Dead Cells"]);
+    assert!(candidates[0].evidence.contains("Roguelike"));
+}
@@ -0,0 +1,234 @@
+mod common;
+
+use common::{database_url, generate_test_discord_id, generate_test_steam_id};
+use game_recommender::bot::{Bot, DEFAULT_LIBRARY_TTL};
+use game_recommender::database::db;
+use game_recommender::llm::{GeminiProvider, LLMClient};
+use game_recommender::steam::SteamClient;
+use game_recommender::store::{StoreClient, STEAMSPY_API_URL, STORE_API_URL};
+use std::time::Duration;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+/// A bot whose Steam requests go to `steam_url`
+fn test_bot(connection: &sqlx::PgPool, steam_url: &str) -> Bot {
+    Bot {
+        database: connection.clone(),
+        steam: SteamClient::new(steam_url, "test_api_key"),
+        llm_client: LLMClient::new(GeminiProvider::new("test_llm_key", None, None)),
+        store: StoreClient::new(STORE_API_URL, STEAMSPY_API_URL),
+        guild_settings: Default::default(),
+        prefix_commands: false,
+        library_ttl: DEFAULT_LIBRARY_TTL,
+        caches: Default::default(),
+        llm_limits: Default::default(),
+    }
+}
+
+// Sync runs are global, so every step shares one test to keep runs from overlapping
+#[tokio::test]
+async fn test_sync_runs_resume_and_back_off() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+
+    // Close anything an earlier aborted test run left open
+    if let Some(run_id) = db::get_unfinished_sync_run(&connection).await.unwrap() {
+        db::finish_sync_run(&connection, run_id).await.unwrap();
+    }
+
+    let synced = generate_test_steam_id();
+    let failing = generate_test_steam_id();
+    let interrupted = generate_test_steam_id();
+    let synced_discord_id = generate_test_discord_id();
+    let failing_discord_id = generate_test_discord_id();
+    for (steam_id, discord_id) in [
+        (&synced, synced_discord_id),
+        (&failing, failing_discord_id),
+        (&interrupted, generate_test_discord_id()),
+    ] {
+        db::link_steam(&connection, "test_user", discord_id, steam_id)
+            .await
+            .expect("Failed to link steam");
+    }
+
+    let run_id = db::start_sync_run(&connection)
+        .await
+        .expect("Failed to start sync run");
+    let pending = db::get_pending_sync_steam_ids(&connection, run_id)
+        .await
+        .expect("Failed to load pending steam ids");
+    assert!(pending.contains(&synced));
+    assert!(pending.contains(&failing));
+    assert!(pending.contains(&interrupted));
+
+    db::record_sync_success(&connection, Some(run_id), &synced)
+        .await
+        .expect("Failed to record success");
+    db::record_sync_failure(&connection, run_id, &failing, "Steam API error: status 500")
+        .await
+        .expect("Failed to record failure");
+
+    // A restart picks the same run back up with only the account it never reached
+    assert_eq!(
+        db::get_unfinished_sync_run(&connection).await.unwrap(),
+        Some(run_id)
+    );
+    let pending = db::get_pending_sync_steam_ids(&connection, run_id)
+        .await
+        .expect("Failed to load pending steam ids");
+    assert!(!pending.contains(&synced));
+    assert!(!pending.contains(&failing));
+    assert!(pending.contains(&interrupted));
+
+    db::record_sync_success(&connection, Some(run_id), &interrupted)
+        .await
+        .expect("Failed to record success");
+    let counts = db::finish_sync_run(&connection, run_id)
+        .await
+        .expect("Failed to finish sync run");
+    assert_eq!(counts, (2, 1));
+    assert_eq!(
+        db::get_unfinished_sync_run(&connection).await.unwrap(),
+        None
+    );
+
+    // The failed account waits out its backoff; everyone else is due again
+    let next_run_id = db::start_sync_run(&connection)
+        .await
+        .expect("Failed to start sync run");
+    assert_ne!(next_run_id, run_id);
+    let pending = db::get_pending_sync_steam_ids(&connection, next_run_id)
+        .await
+        .expect("Failed to load pending steam ids");
+    assert!(pending.contains(&synced));
+    assert!(!pending.contains(&failing));
+    assert!(pending.contains(&interrupted));
+
+    // Unlinking takes the sync status with it
+    db::unlink_steam(&connection, synced_discord_id)
+        .await
+        .expect("Failed to unlink");
+    let remaining: i64 =
+        sqlx::query_scalar("SELECT COUNT(*) FROM sync_status WHERE steam_id = $1;")
+            .bind(&synced)
+            .fetch_one(&connection)
+            .await
+            .unwrap();
+    assert_eq!(remaining, 0);
+
+    // Relinking starts the new account with a clean slate
+    let relinked = generate_test_steam_id();
+    db::relink_steam(&connection, failing_discord_id, &relinked)
+        .await
+        .expect("Failed to relink");
+    let pending = db::get_pending_sync_steam_ids(&connection, next_run_id)
+        .await
+        .expect("Failed to load pending steam ids");
+    assert!(pending.contains(&relinked));
+
+    db::finish_sync_run(&connection, next_run_id).await.unwrap();
+}
+
+#[tokio::test]
+async fn test_only_one_sync_holds_the_lock() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+
+    let lock = db::try_lock_sync(&connection)
+        .await
+        .expect("Failed to take the sync lock")
+        .expect("Sync lock already held");
+    assert!(db::try_lock_sync(&connection)
+        .await
+        .expect("Failed to take the sync lock")
+        .is_none());
+
+    // Dropping the lock closes its connection, which Postgres notices shortly after
+    drop(lock);
+    let mut relocked = None;
+    for _ in 0..50 {
+        relocked = db::try_lock_sync(&connection)
+            .await
+            .expect("Failed to take the sync lock");
+        if relocked.is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert!(relocked.is_some());
+}
+
+#[tokio::test]
+async fn test_manual_sync_cooldown() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let discord_id = generate_test_discord_id();
+    db::link_steam(
+        &connection,
+        "test_user",
+        discord_id,
+        &generate_test_steam_id(),
+    )
+    .await
+    .expect("Failed to link steam");
+
+    let cooldown = Duration::from_secs(15 * 60);
+    let first = db::claim_manual_sync(&connection, discord_id, cooldown)
+        .await
+        .expect("Failed to claim sync");
+    assert_eq!(first, None);
+
+    let remaining = db::claim_manual_sync(&connection, discord_id, cooldown)
+        .await
+        .expect("Failed to claim sync")
+        .expect("Second sync should be on cooldown");
+    assert!(remaining > Duration::from_secs(14 * 60));
+    assert!(remaining <= cooldown);
+
+    // Once the cooldown has passed the user can sync again
+    let claimed = db::claim_manual_sync(&connection, discord_id, Duration::ZERO)
+        .await
+        .expect("Failed to claim sync");
+    assert_eq!(claimed, None);
+}
+
+#[tokio::test]
+async fn test_failed_manual_sync_keeps_cooldown_free() {
+    let connection = sqlx::PgPool::connect(&database_url()).await.unwrap();
+    let mock_server = MockServer::start().await;
+    let discord_id = generate_test_discord_id();
+    let steam_id = generate_test_steam_id();
+    db::link_steam(&connection, "test_user", discord_id, &steam_id)
+        .await
+        .expect("Failed to link steam");
+
+    // Steam is down for every attempt of the first sync
+    Mock::given(method("GET"))
+        .and(path("/IPlayerService/GetOwnedGames/v1/"))
+        .respond_with(ResponseTemplate::new(503).insert_header("Retry-After", "0"))
+        .up_to_n_times(5)
+        .mount(&mock_server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path("/IPlayerService/GetOwnedGames/v1/"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+            "response": {
+                "game_count": 1,
+                "games": [{ "appid": 10, "name": "Test Game 1", "playtime_forever": 60 }]
+            }
+        })))
+        .mount(&mock_server)
+        .await;
+
+    let bot = test_bot(&connection, &mock_server.uri());
+    assert!(bot.manual_sync(discord_id, &steam_id).await.is_err());
+
+    // Trying again right away is allowed, and a successful sync starts the cooldown
+    let changes = bot
+        .manual_sync(discord_id, &steam_id)
+        .await
+        .expect("Retried sync was refused");
+    assert_eq!(changes.games_added, 1);
+    let refused = bot
+        .manual_sync(discord_id, &steam_id)
+        .await
+        .expect_err("Sync during the cooldown was allowed");
+    assert!(refused.starts_with("⏳"));
+}
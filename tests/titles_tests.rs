@@ -0,0 +1,64 @@
+use game_recommender::steam::SteamGame;
+use game_recommender::titles::*;
+
+fn owned(appid: u32, name: &str) -> SteamGame {
+    SteamGame {
+        appid,
+        name: name.to_string(),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_normalize_title() {
+    assert_eq!(
+        normalize_title("The Witcher® 3: Wild Hunt - Game of the Year Edition"),
+        "witcher 3 wild hunt"
+    );
+    assert_eq!(normalize_title("Final Fantasy VII"), "final fantasy 7");
+    assert_eq!(normalize_title("Baldur's Gate 3"), "baldurs gate 3");
+    assert_eq!(normalize_title("DARK SOULS™ III"), "dark souls 3");
+    assert_eq!(normalize_title("Mega Man X"), "mega man x");
+    assert_eq!(normalize_title("Borderlands GOTY"), "borderlands");
+    assert_eq!(normalize_title("Skyrim Special Edition"), "skyrim");
+    assert_eq!(
+        normalize_title("Death Stranding Director's Cut"),
+        "death stranding"
+    );
+    // Edition words only count at the end of a title
+    assert_eq!(normalize_title("The Ultimate Doom"), "ultimate doom");
+    assert_eq!(normalize_title("Cut the Rope"), "cut the rope");
+    assert_eq!(normalize_title("Gold Rush: The Game"), "gold rush the game");
+    assert_eq!(normalize_title("Special Edition"), "special edition");
+}
+
+#[test]
+fn test_owned_library_matching() {
+    let library = OwnedLibrary::new(&[
+        owned(292030, "The Witcher 3: Wild Hunt"),
+        owned(400, "Portal"),
+        owned(374320, "DARK SOULS™ III"),
+        owned(1091500, "Star Wars Jedi: Fallen Order"),
+    ]);
+
+    assert!(library.owns("Witcher 3", None));
+    assert!(library.owns("The Witcher 3: Wild Hunt – Complete Edition", None));
+    assert!(library.owns("Dark Souls 3", None));
+    assert!(library.owns("Some Renamed Title", Some(400)));
+
+    assert!(!library.owns("Portal 2", None));
+    assert!(!library.owns("Star Wars Jedi: Survivor", None));
+    assert!(!library.owns("Hades", Some(1145360)));
+}
+
+#[test]
+fn test_owned_library_keeps_games_that_only_share_words() {
+    let library = OwnedLibrary::new(&[
+        owned(2280, "The Ultimate Doom"),
+        owned(20500, "Cut the Rope"),
+    ]);
+
+    assert!(!library.owns("DOOM", None));
+    assert!(!library.owns("Rope", None));
+    assert!(library.owns("Cut the Rope: Complete Edition", None));
+}
@@ -0,0 +1,74 @@
+use game_recommender::steam::SteamGame;
+use game_recommender::together::*;
+use std::collections::HashMap;
+
+fn game(appid: u32, name: &str, playtime_forever: u32) -> SteamGame {
+    SteamGame {
+        appid,
+        name: name.to_string(),
+        playtime_forever,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_rank_shared_games() {
+    let libraries = vec![
+        vec![
+            game(1, "Deep Rock Galactic", 3000),
+            game(2, "Stardew Valley", 60_000),
+            game(3, "Solo Only", 600),
+            game(4, "Unplayed Together", 0),
+        ],
+        vec![
+            game(1, "Deep Rock Galactic", 2400),
+            game(2, "Stardew Valley", 0),
+            game(4, "Unplayed Together", 0),
+        ],
+        vec![
+            game(1, "Deep Rock Galactic", 1800),
+            game(2, "Stardew Valley", 30),
+            game(3, "Solo Only", 600),
+            game(4, "Unplayed Together", 0),
+        ],
+    ];
+    let categories = HashMap::from([
+        (
+            1,
+            vec!["Online Co-op".to_string(), "Single-player".to_string()],
+        ),
+        (4, vec!["Single-player".to_string()]),
+    ]);
+
+    let shared = rank_shared_games(&libraries, &categories);
+    let names: Vec<&str> = shared.iter().map(|g| g.name.as_str()).collect();
+
+    // One member's 1,000 hours don't beat a game all three play
+    assert_eq!(
+        names,
+        vec!["Deep Rock Galactic", "Stardew Valley", "Unplayed Together"]
+    );
+    assert_eq!(shared[0].minutes, vec![3000, 2400, 1800]);
+    assert_eq!(shared[0].multiplayer, Some(true));
+    assert_eq!(shared[1].multiplayer, None);
+    assert_eq!(shared[2].multiplayer, Some(false));
+    assert_eq!(shared[2].score, 0.0);
+}
+
+#[test]
+fn test_rank_shared_games_without_overlap() {
+    let libraries = vec![vec![game(1, "A", 60)], vec![game(2, "B", 60)]];
+    assert!(rank_shared_games(&libraries, &HashMap::new()).is_empty());
+    assert!(rank_shared_games(&[], &HashMap::new()).is_empty());
+}
+
+#[test]
+fn test_is_multiplayer() {
+    assert!(is_multiplayer(&["Online PvP".to_string()]));
+    assert!(is_multiplayer(&["Multi-player".to_string()]));
+    assert!(is_multiplayer(&["Shared/Split Screen Co-op".to_string()]));
+    assert!(!is_multiplayer(&[
+        "Single-player".to_string(),
+        "Steam Achievements".to_string()
+    ]));
+}